@@ -0,0 +1,357 @@
+//! A terminal player for the universe.
+//!
+//! ```text
+//! gol-tui [pattern] [--padding N]
+//! ```
+//!
+//! The pattern is a file in any supported format or the name of a pattern in
+//! the bundled library. Without one, the universe is filled at random.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, style, terminal};
+
+use gol_wasm::config::UniverseConfig;
+use gol_wasm::library;
+use gol_wasm::utils;
+use gol_wasm::universe::{Cell, Universe};
+
+const USAGE: &str = "usage: gol-tui [pattern] [--padding N]";
+const HELP: &str = "space play  n step  b back  arrows pan  +/- zoom  [/] speed  m glyphs  c centre  r restart  q quit";
+
+const DEFAULT_PADDING: u32 = 32;
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+const MIN_DELAY: Duration = Duration::from_millis(1);
+const MAX_DELAY: Duration = Duration::from_millis(2000);
+const MAX_SCALE: u32 = 64;
+
+/// The characters used to draw cells.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Glyphs {
+    /// `▀`, `▄` and `█`: one dot across and two down per character.
+    HalfBlock,
+    /// Braille patterns: two dots across and four down per character.
+    Braille,
+}
+
+impl Glyphs {
+    /// Dots across and down in a character.
+    fn dots(self) -> (u16, u16) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4),
+        }
+    }
+
+    /// The character for a block of dots, with `alive(x, y)` telling whether
+    /// the dot at `(x, y)` within the character is set.
+    fn glyph(self, alive: impl Fn(u16, u16) -> bool) -> char {
+        match self {
+            Glyphs::HalfBlock => match (alive(0, 0), alive(0, 1)) {
+                (false, false) => ' ',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (true, true) => '█',
+            },
+            Glyphs::Braille => {
+                // bit of each dot, indexed by [y][x]
+                const BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+                let mut bits = 0;
+                for (y, row) in BITS.iter().enumerate() {
+                    for (x, &bit) in row.iter().enumerate() {
+                        if alive(x as u16, y as u16) {
+                            bits |= bit;
+                        }
+                    }
+                }
+                std::char::from_u32(0x2800 + bits).unwrap()
+            },
+        }
+    }
+}
+
+struct Player {
+    config: UniverseConfig,
+    universe: Universe,
+    glyphs: Glyphs,
+    /// Cells across and down in each dot. A dot is set if any of its cells
+    /// is alive.
+    scale: u32,
+    /// The cell at the top left of the view.
+    origin: (i64, i64),
+    playing: bool,
+    delay: Duration,
+}
+
+impl Player {
+    fn new(config: UniverseConfig) -> io::Result<Self> {
+        let mut player = Player {
+            universe: config.clone().construct(),
+            config,
+            glyphs: Glyphs::HalfBlock,
+            scale: 1,
+            origin: (0, 0),
+            playing: false,
+            delay: DEFAULT_DELAY,
+        };
+        player.restart()?;
+        Ok(player)
+    }
+
+    /// Rebuild the universe from its configuration.
+    fn restart(&mut self) -> io::Result<()> {
+        let (columns, rows) = self.view_size()?;
+        self.universe = self.config.clone().construct();
+        self.universe
+            .connect_headless(columns, rows)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.centre()
+    }
+
+    /// Characters across and down available for the universe, leaving a line
+    /// for the status bar.
+    fn screen_size() -> io::Result<(u16, u16)> {
+        let (columns, rows) = terminal::size()?;
+        Ok((columns, rows.saturating_sub(1)))
+    }
+
+    /// Cells across and down in the view.
+    fn view_size(&self) -> io::Result<(u32, u32)> {
+        let (columns, rows) = Player::screen_size()?;
+        let (dots_across, dots_down) = self.glyphs.dots();
+        Ok((
+            (columns * dots_across) as u32 * self.scale,
+            (rows * dots_down) as u32 * self.scale,
+        ))
+    }
+
+    fn centre(&mut self) -> io::Result<()> {
+        let (columns, rows) = self.view_size()?;
+        self.origin = (
+            self.universe.width() as i64 / 2 - columns as i64 / 2,
+            self.universe.height() as i64 / 2 - rows as i64 / 2,
+        );
+        Ok(())
+    }
+
+    /// Move the view by a fraction of its size.
+    fn pan(&mut self, dx: i64, dy: i64) -> io::Result<()> {
+        let (columns, rows) = self.view_size()?;
+        self.origin.0 += dx * (columns as i64 / 4).max(1);
+        self.origin.1 += dy * (rows as i64 / 4).max(1);
+        Ok(())
+    }
+
+    /// Change the scale, keeping the centre of the view in place.
+    fn zoom(&mut self, scale: u32) -> io::Result<()> {
+        let (columns, rows) = self.view_size()?;
+        let centre = (self.origin.0 + columns as i64 / 2, self.origin.1 + rows as i64 / 2);
+        self.scale = scale.clamp(1, MAX_SCALE);
+        let (columns, rows) = self.view_size()?;
+        self.origin = (centre.0 - columns as i64 / 2, centre.1 - rows as i64 / 2);
+        Ok(())
+    }
+
+    fn set_glyphs(&mut self, glyphs: Glyphs) -> io::Result<()> {
+        let (columns, rows) = self.view_size()?;
+        let centre = (self.origin.0 + columns as i64 / 2, self.origin.1 + rows as i64 / 2);
+        self.glyphs = glyphs;
+        let (columns, rows) = self.view_size()?;
+        self.origin = (centre.0 - columns as i64 / 2, centre.1 - rows as i64 / 2);
+        Ok(())
+    }
+
+    /// Whether any cell of the dot at `(x, y)` in the view is alive.
+    fn dot(&self, x: i64, y: i64) -> bool {
+        let scale = self.scale as i64;
+        let (col, row) = (self.origin.0 + x * scale, self.origin.1 + y * scale);
+        // nothing to gain from scanning the universe more than once
+        let rows = scale.min(self.universe.height() as i64);
+        let cols = scale.min(self.universe.width() as i64);
+        (0..rows).any(|dy| {
+            (0..cols).any(|dx| self.universe.cell((row + dy) as i32, (col + dx) as i32) == Cell::Alive)
+        })
+    }
+
+    fn population(&self) -> usize {
+        (0..self.universe.height() as i32)
+            .map(|row| {
+                (0..self.universe.width() as i32)
+                    .filter(|&col| self.universe.cell(row, col) == Cell::Alive)
+                    .count()
+            })
+            .sum()
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let (columns, rows) = Player::screen_size()?;
+        let (dots_across, dots_down) = self.glyphs.dots();
+        queue!(out, cursor::MoveTo(0, 0))?;
+        for row in 0..rows {
+            let line = (0..columns)
+                .map(|col| {
+                    self.glyphs.glyph(|x, y| {
+                        self.dot((col * dots_across + x) as i64, (row * dots_down + y) as i64)
+                    })
+                })
+                .collect::<String>();
+            queue!(out, cursor::MoveTo(0, row), style::Print(line))?;
+        }
+
+        let status = format!(
+            "gen {}  pop {}  {}  {}ms  1:{}  {}  |  {}",
+            self.universe.generation(),
+            self.population(),
+            if self.playing { "playing" } else { "paused" },
+            self.delay.as_millis(),
+            self.scale,
+            if self.glyphs == Glyphs::Braille { "braille" } else { "half-block" },
+            HELP,
+        );
+        let status = status.chars().take(columns as usize).collect::<String>();
+        queue!(
+            out,
+            cursor::MoveTo(0, rows),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            style::PrintStyledContent(style::Stylize::reverse(status))
+        )?;
+        out.flush()
+    }
+
+    /// Handle a key press, returning false to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> io::Result<bool> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+            KeyCode::Char(' ') => self.playing = !self.playing,
+            KeyCode::Char('n') | KeyCode::Char('.') => {
+                self.playing = false;
+                self.universe.tick();
+            },
+            KeyCode::Char('b') | KeyCode::Char(',') => {
+                self.playing = false;
+                self.universe.step_back();
+            },
+            KeyCode::Left | KeyCode::Char('h') => self.pan(-1, 0)?,
+            KeyCode::Right | KeyCode::Char('l') => self.pan(1, 0)?,
+            KeyCode::Up | KeyCode::Char('k') => self.pan(0, -1)?,
+            KeyCode::Down | KeyCode::Char('j') => self.pan(0, 1)?,
+            KeyCode::Char('+') | KeyCode::Char('=') => self.zoom(self.scale / 2)?,
+            KeyCode::Char('-') | KeyCode::Char('_') => self.zoom(self.scale * 2)?,
+            KeyCode::Char('[') => self.delay = (self.delay * 2).min(MAX_DELAY),
+            KeyCode::Char(']') => self.delay = (self.delay / 2).max(MIN_DELAY),
+            KeyCode::Char('m') => {
+                let glyphs = match self.glyphs {
+                    Glyphs::HalfBlock => Glyphs::Braille,
+                    Glyphs::Braille => Glyphs::HalfBlock,
+                };
+                self.set_glyphs(glyphs)?;
+            },
+            KeyCode::Char('c') => self.centre()?,
+            KeyCode::Char('r') => self.restart()?,
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn run(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let mut last_tick = Instant::now();
+        loop {
+            self.draw(out)?;
+            let timeout = if self.playing {
+                self.delay.saturating_sub(last_tick.elapsed())
+            } else {
+                Duration::from_secs(60)
+            };
+            if event::poll(timeout)? {
+                match event::read()? {
+                    Event::Key(key) if key.kind != KeyEventKind::Release && !self.handle_key(key)? => {
+                        return Ok(());
+                    },
+                    Event::Resize(_, _) => queue!(out, terminal::Clear(terminal::ClearType::All))?,
+                    _ => (),
+                }
+            }
+            if self.playing && last_tick.elapsed() >= self.delay {
+                self.universe.tick();
+                last_tick = Instant::now();
+            }
+        }
+    }
+}
+
+/// Puts the terminal into raw mode on the alternate screen, and restores it
+/// when dropped, including when unwinding from a panic. Logging is off in the
+/// meantime, since stderr would be written over the screen.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        utils::set_log_enabled(false);
+        // restores the terminal and logging if entering fails part way
+        let raw = RawTerminal;
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(raw)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+        utils::set_log_enabled(true);
+    }
+}
+
+fn config(args: &[String]) -> Result<UniverseConfig, String> {
+    let mut pattern = None;
+    let mut padding = DEFAULT_PADDING;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--padding" => {
+                let value = args.next().ok_or("missing value for --padding")?;
+                padding = value.parse().map_err(|_| format!("invalid value for --padding: `{}`", value))?;
+            },
+            _ if pattern.is_none() => pattern = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    let config = UniverseConfig::new().set_padding(padding);
+    match pattern {
+        Some(pattern) if Path::new(pattern).is_file() => {
+            let text = fs::read_to_string(pattern).map_err(|err| format!("cannot read {}: {}", pattern, err))?;
+            Ok(config.set_pattern_input(&text))
+        },
+        Some(pattern) if library::find(pattern).is_some() => config.set_named_input(pattern),
+        Some(pattern) => Err(format!("no file or library pattern named `{}`", pattern)),
+        None => Ok(config.set_random_input()),
+    }
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let config = match config(&args) {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(1);
+        },
+    };
+
+    let result = RawTerminal::enter().and_then(|_terminal| {
+        let mut out = io::stdout();
+        Player::new(config)?.run(&mut out)
+    });
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
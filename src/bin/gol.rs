@@ -0,0 +1,293 @@
+//! Headless simulation and conversion of patterns.
+//!
+//! ```text
+//! gol run <pattern> [--gens N] [--padding N] [--out FILE] [--format FORMAT]
+//! gol convert <input> <output> [--format FORMAT]
+//! gol info <pattern> [--max-period N]
+//! gol soup [--seed N] [--symmetry SYMMETRY] [--out FILE] [--format FORMAT]
+//! ```
+//!
+//! Input formats are detected from the contents. Output formats are `rle`,
+//! `cells`, `life105`, `life106` and `mc`, taken from `--format` or else the
+//! extension of the output file, and default to RLE.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use gol_wasm::config::UniverseConfig;
+use gol_wasm::pattern::Pattern;
+use gol_wasm::rle_loader::{self, Format};
+use gol_wasm::{life105, life106, macrocell, plaintext, soup};
+
+const USAGE: &str = "usage:
+    gol run <pattern> [--gens N] [--padding N] [--out FILE] [--format FORMAT]
+    gol convert <input> <output> [--format FORMAT]
+    gol info <pattern> [--max-period N]
+    gol soup [--seed N] [--symmetry SYMMETRY] [--out FILE] [--format FORMAT]";
+
+const DEFAULT_GENERATIONS: u32 = 1;
+const DEFAULT_MAX_PERIOD: u32 = 1000;
+
+/// The positional arguments and `--name value` options of a command.
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args.next().ok_or_else(|| format!("missing value for --{}", name))?;
+                    options.insert(String::from(name), value);
+                },
+                None => positional.push(arg),
+            }
+        }
+        Ok(Args { positional, options })
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("missing {}", name))
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.option(name) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid value for --{}: `{}`", name, value)),
+            None => Ok(None),
+        }
+    }
+
+    /// Fail on any option that the command does not take.
+    fn expect_options(&self, names: &[&str]) -> Result<(), String> {
+        match self.options.keys().find(|name| !names.contains(&name.as_str())) {
+            Some(name) => Err(format!("unknown option --{}", name)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    let result = Args::parse(args).and_then(|args| match command.as_deref() {
+        Some("run") => run(&args),
+        Some("convert") => convert(&args),
+        Some("info") => info(&args),
+        Some("soup") => soup(&args),
+        Some(command) => Err(format!("unknown command `{}`", command)),
+        None => Err(String::from("missing command")),
+    });
+    if let Err(message) = result {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(1);
+    }
+}
+
+fn read(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| format!("cannot read {}: {}", path, err))
+}
+
+/// Parse the text read from `path` in whichever format it is written in.
+fn load(path: &str, text: &str) -> Result<Pattern, String> {
+    rle_loader::load_any(text).map_err(|err| format!("cannot load {}: {}", path, err))
+}
+
+/// The output format named by `--format`, else by the extension of `path`.
+fn output_format(format: Option<&str>, path: Option<&str>) -> Result<Format, String> {
+    let extension = path.and_then(|path| Path::new(path).extension()).and_then(|ext| ext.to_str());
+    match format.or(extension) {
+        Some("rle") | None => Ok(Format::Rle),
+        Some("cells") => Ok(Format::Plaintext),
+        Some("life105") => Ok(Format::Life105),
+        Some("life106") | Some("lif") | Some("life") => Ok(Format::Life106),
+        Some("mc") => Ok(Format::Macrocell),
+        Some(other) => Err(format!("unknown output format `{}`", other)),
+    }
+}
+
+fn save(pattern: &Pattern, format: Format) -> String {
+    match format {
+        Format::Rle => rle_loader::save(pattern, None),
+        Format::Plaintext => plaintext::save(pattern, None),
+        Format::Life105 => life105::save(pattern),
+        Format::Life106 => life106::save(pattern),
+        Format::Macrocell => macrocell::save(&macrocell::Macrocell::from_pattern(pattern)),
+    }
+}
+
+/// Write to `path`, or to stdout if there is no path.
+fn write(text: &str, path: Option<&str>) -> Result<(), String> {
+    match path {
+        Some(path) => fs::write(path, text).map_err(|err| format!("cannot write {}: {}", path, err)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        },
+    }
+}
+
+/// Run a pattern on the unbounded plane, or on a torus with the given padding
+/// around the pattern if `--padding` is given.
+fn run(args: &Args) -> Result<(), String> {
+    args.expect_options(&["gens", "padding", "out", "format"])?;
+    let path = args.positional(0, "pattern")?;
+    let text = read(path)?;
+    check_rule(&text)?;
+    let generations = args.number("gens")?.unwrap_or(DEFAULT_GENERATIONS);
+    let padding = args.number("padding")?;
+    let out = args.option("out");
+    let format = output_format(args.option("format"), out)?;
+
+    let pattern = match padding {
+        Some(padding) => {
+            let mut universe = UniverseConfig::new()
+                .set_pattern_input(&text)
+                .set_padding(padding)
+                .set_history_limit(0)
+                .construct();
+            universe.connect_headless(0, 0).map_err(|err| format!("cannot load {}: {}", path, err))?;
+            for _ in 0..generations {
+                universe.tick();
+            }
+            universe.to_pattern()
+        },
+        None => {
+            // trimming after every generation keeps the box no larger than
+            // the pattern needs
+            let mut pattern = load(path, &text)?.trimmed();
+            for _ in 0..generations {
+                pattern = pattern.advanced(1).trimmed();
+            }
+            pattern
+        },
+    };
+    write(&save(&pattern.trimmed(), format), out)
+}
+
+fn convert(args: &Args) -> Result<(), String> {
+    args.expect_options(&["format"])?;
+    let input = args.positional(0, "input")?;
+    let pattern = load(input, &read(input)?)?;
+    let out = args.positional(1, "output")?;
+    let format = output_format(args.option("format"), Some(out))?;
+    write(&save(&pattern, format), Some(out))
+}
+
+fn info(args: &Args) -> Result<(), String> {
+    args.expect_options(&["max-period"])?;
+    let path = args.positional(0, "pattern")?;
+    let text = read(path)?;
+    let max_period = args.number("max-period")?.unwrap_or(DEFAULT_MAX_PERIOD);
+    check_rule(&text)?;
+    let pattern = load(path, &text)?;
+
+    println!("format: {:?}", rle_loader::detect_format(&text));
+    println!("size: {}x{}", pattern.width(), pattern.height());
+    println!("population: {}", pattern.population());
+    println!("rule: B3/S23");
+    match period(&pattern, max_period) {
+        Some(Evolution::Dies(generation)) => println!("period: dies out at generation {}", generation),
+        Some(Evolution::Repeats { period, dx: 0, dy: 0 }) => println!("period: {}", period),
+        Some(Evolution::Repeats { period, dx, dy }) => {
+            println!("period: {}, moving ({}, {}) each period", period, dx, dy)
+        },
+        None => println!("period: none within {} generations", max_period),
+    }
+    Ok(())
+}
+
+/// The rule given in a pattern's header, if its format has one.
+fn read_rule(text: &str) -> Option<String> {
+    match rle_loader::detect_format(text) {
+        Format::Rle => rle_loader::read_rule(text),
+        // a malformed file is reported when the pattern is loaded
+        Format::Macrocell => macrocell::load(text).ok().and_then(|macrocell| macrocell.rule),
+        Format::Life105 => text
+            .lines()
+            .find_map(|line| line.strip_prefix("#R"))
+            .map(|rule| String::from(rule.trim())),
+        Format::Plaintext | Format::Life106 => None,
+    }
+}
+
+/// Fail unless the pattern is for Conway's Life, the only rule simulated.
+/// The rule may be written as `B3/S23` or in the older `23/3` form.
+fn check_rule(text: &str) -> Result<(), String> {
+    match read_rule(text) {
+        Some(rule) if !matches!(rule.to_ascii_uppercase().as_str(), "B3/S23" | "S23/B3" | "23/3") => {
+            Err(format!("unsupported rule `{}`, only B3/S23 is simulated", rule))
+        },
+        _ => Ok(()),
+    }
+}
+
+fn soup(args: &Args) -> Result<(), String> {
+    args.expect_options(&["seed", "symmetry", "out", "format"])?;
+    let symmetry = args.option("symmetry").unwrap_or("C1").parse::<soup::Symmetry>()?;
+    let mut rng = match args.number("seed")? {
+        Some(seed) => soup::SoupRng::new(seed),
+        None => soup::SoupRng::from_entropy(),
+    };
+    let out = args.option("out");
+    let format = output_format(args.option("format"), out)?;
+    let soup = soup::generate(symmetry, &mut rng);
+    write(&save(&Pattern::from_cells(soup.width, soup.height, &soup.cells), format), out)
+}
+
+/// How a pattern evolves on the unbounded plane.
+enum Evolution {
+    /// The pattern has no alive cells from this generation on.
+    Dies(u32),
+    /// The pattern returns to its starting shape every `period` generations,
+    /// moved by `(dx, dy)`.
+    Repeats { period: u32, dx: i64, dy: i64 },
+}
+
+/// Step the pattern until it dies or returns to its starting shape, giving up
+/// after `max_generations`.
+fn period(pattern: &Pattern, max_generations: u32) -> Option<Evolution> {
+    let (start, start_x, start_y) = trim(pattern, 0, 0);
+    if start.population() == 0 {
+        return Some(Evolution::Dies(0));
+    }
+    let (mut current, mut x, mut y) = (start.clone(), start_x, start_y);
+    for generation in 1..=max_generations {
+        // `advanced` grows the box by a cell on every side
+        let (next, next_x, next_y) = trim(&current.advanced(1), x - 1, y - 1);
+        if next.population() == 0 {
+            return Some(Evolution::Dies(generation));
+        }
+        if next == start {
+            return Some(Evolution::Repeats { period: generation, dx: next_x - start_x, dy: next_y - start_y });
+        }
+        current = next;
+        x = next_x;
+        y = next_y;
+    }
+    None
+}
+
+/// Trim a pattern whose top left corner is at `(x, y)`, along with the
+/// position of the trimmed pattern.
+fn trim(pattern: &Pattern, x: i64, y: i64) -> (Pattern, i64, i64) {
+    let min_x = pattern.cells().iter().map(|&(x, _)| x).min().unwrap_or(0);
+    let min_y = pattern.cells().first().map(|&(_, y)| y).unwrap_or(0);
+    (pattern.trimmed(), x + min_x as i64, y + min_y as i64)
+}
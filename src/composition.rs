@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "web")]
+use wasm_bindgen::prelude::*;
+
+use crate::pattern::Pattern;
+use crate::rle_loader;
+use crate::transform::Transform;
+
+/// How a pasted pattern combines with the cells already underneath it.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PasteMode {
+    /// Alive cells of the pattern are set, everything else is kept.
+    Or,
+    /// Alive cells of the pattern toggle the cells underneath.
+    Xor,
+    /// The pattern's box replaces the cells underneath, dead cells included.
+    Copy,
+}
+
+/// One pattern placed in a composition.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Placement {
+    /// The pattern, in any supported format.
+    pub pattern: String,
+    /// The column of the top left cell of the transformed pattern.
+    pub x: i64,
+    /// The row of the top left cell of the transformed pattern.
+    pub y: i64,
+    pub transform: Transform,
+    /// The number of generations to run the pattern forward before placing
+    /// it. The pattern is advanced in place, so a spaceship moves away from
+    /// `(x, y)` just as it would after that many ticks.
+    pub phase: u32,
+}
+
+impl Placement {
+    /// Load, transform and advance the pattern, returning it along with the
+    /// position of its top left cell.
+    pub fn resolve(&self) -> Result<(Pattern, i64, i64), String> {
+        let pattern = rle_loader::load_any(&self.pattern)?.transformed(self.transform);
+        if self.phase == 0 {
+            return Ok((pattern, self.x, self.y));
+        }
+        let phase = self.phase as i64;
+        Ok((pattern.advanced(self.phase), self.x - phase, self.y - phase))
+    }
+}
+
+/// Combine placements into a single pattern covering all of their alive
+/// cells. Overlapping placements are combined with `PasteMode::Or`.
+pub fn compose(placements: &[Placement]) -> Result<Pattern, String> {
+    let mut coordinates = Vec::new();
+    for placement in placements {
+        let (pattern, x, y) = placement.resolve()?;
+        coordinates.extend(pattern.cells().iter().map(|&(cx, cy)| (x + cx as i64, y + cy as i64)));
+    }
+    Ok(Pattern::from_coordinates(&coordinates))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &str = "x = 3, y = 3\nbo$2bo$3o!";
+
+    fn placement(pattern: &str, x: i64, y: i64, transform: Transform, phase: u32) -> Placement {
+        Placement {
+            pattern: String::from(pattern),
+            x,
+            y,
+            transform,
+            phase,
+        }
+    }
+
+    /// The alive cells of a resolved placement, relative to the origin.
+    fn alive(placement: &Placement) -> Vec<(i64, i64)> {
+        let (pattern, x, y) = placement.resolve().unwrap();
+        pattern.cells().iter().map(|&(cx, cy)| (x + cx as i64, y + cy as i64)).collect()
+    }
+
+    #[test]
+    fn advancing_grows_the_box_around_the_pattern() {
+        let blinker = Pattern::new(3, 1, vec![(0, 0), (1, 0), (2, 0)]);
+        let advanced = blinker.advanced(1);
+        assert_eq!((advanced.width(), advanced.height()), (5, 3));
+        assert_eq!(advanced.cells(), &[(2, 0), (2, 1), (2, 2)]);
+        assert_eq!(blinker.advanced(2).cells(), &[(2, 2), (3, 2), (4, 2)]);
+        assert_eq!(blinker.advanced(0), blinker);
+    }
+
+    #[test]
+    fn a_glider_four_generations_on_is_shifted_diagonally() {
+        let advanced = alive(&placement(GLIDER, 10, 20, Transform::Identity, 4));
+        assert_eq!(advanced, alive(&placement(GLIDER, 11, 21, Transform::Identity, 0)));
+    }
+
+    #[test]
+    fn placements_are_transformed_before_they_are_moved() {
+        let blinker = "x = 3, y = 1\n3o!";
+        assert_eq!(alive(&placement(blinker, 5, 7, Transform::Rotate90, 0)), vec![(5, 7), (5, 8), (5, 9)]);
+        // the glider flipped to travel up and left keeps its top left corner
+        assert_eq!(
+            alive(&placement(GLIDER, -3, 4, Transform::Rotate180, 0)),
+            vec![(-3, 4), (-2, 4), (-1, 4), (-3, 5), (-2, 6)]
+        );
+    }
+
+    #[test]
+    fn overlapping_placements_are_combined() {
+        let block = "x = 2, y = 2\n2o$2o!";
+        let pattern = compose(&[
+            placement(block, 0, 0, Transform::Identity, 0),
+            placement(block, 1, 1, Transform::Identity, 0),
+        ])
+        .unwrap();
+        assert_eq!(pattern, Pattern::new(3, 3, vec![(0, 0), (1, 0), (0, 1), (1, 1), (2, 1), (1, 2), (2, 2)]));
+    }
+
+    #[test]
+    fn invalid_placements_are_reported() {
+        let placements = [
+            placement(GLIDER, 0, 0, Transform::Identity, 0),
+            placement("x = 1, y = 1\nz!", 0, 0, Transform::Identity, 0),
+        ];
+        assert_eq!(compose(&placements).err(), Some(String::from("invalid rle character `z`")));
+    }
+}
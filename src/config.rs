@@ -1,33 +1,62 @@
+use crate::composition;
+use crate::library;
+use crate::render::{CellShape, RenderMode, SubCellShading};
 use crate::universe;
+use crate::soup;
+use crate::transform::Transform;
 
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "web")]
 use wasm_bindgen::prelude::*;
 
 const DEFAULT_PADDING: u32 = 0;
 const DEFAULT_CELL_SIZE: u32 = 10;
+const DEFAULT_HISTORY_LIMIT: usize = 16 * 1024 * 1024;
 
 /// The input data from which the universe will be constructed with. 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum UniverseInput {
     Random,
     RleString(String),
+    /// A pattern from the bundled library.
+    Named(String),
+    /// A soup with the given symmetry, reproducible when a seed is given.
+    Soup(soup::Symmetry, Option<u64>),
+    /// Several patterns placed relative to each other.
+    Composition(Vec<composition::Placement>),
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UniverseConfig {
     // construction parameters
     input: UniverseInput,
     padding: u32,
     cell_size: u32,
     override_size: Option<(u32, u32)>,
+    history_limit: usize,
 
     // styling
+    render_mode: RenderMode,
+    sub_cell_shading: SubCellShading,
     pub lines_enabled: bool,
     pub line_width: u32,
     pub border_width: u32,
     pub allow_overflow: bool,
+    /// Device pixels per CSS pixel. Sizes in the configuration and cell sizes
+    /// are in CSS pixels and the canvas is drawn in device pixels.
+    pub device_pixel_ratio: f64,
     line_color: String,
     cell_alive_color: String,
     cell_dead_color: String,
+    selection_color: String,
+    cell_shape: CellShape,
+    corner_radius: u32,
+    cell_gap: u32,
+    glow_radius: u32,
+    glow_color: String,
+    major_line_interval: u32,
+    major_line_color: String,
 }
 
 impl UniverseConfig {
@@ -47,6 +76,18 @@ impl UniverseConfig {
         self.override_size
     }
 
+    pub fn get_history_limit(&self) -> usize {
+        self.history_limit
+    }
+
+
+    pub fn get_render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub fn get_sub_cell_shading(&self) -> SubCellShading {
+        self.sub_cell_shading
+    }
 
     pub fn get_line_color(&self) -> String {
         self.line_color.clone()
@@ -57,9 +98,34 @@ impl UniverseConfig {
     pub fn get_cell_dead_color(&self) -> String {
         self.cell_dead_color.clone()
     }
+    pub fn get_selection_color(&self) -> String {
+        self.selection_color.clone()
+    }
+
+    pub fn get_cell_shape(&self) -> CellShape {
+        self.cell_shape
+    }
+    pub fn get_corner_radius(&self) -> u32 {
+        self.corner_radius
+    }
+    pub fn get_cell_gap(&self) -> u32 {
+        self.cell_gap
+    }
+    pub fn get_glow_radius(&self) -> u32 {
+        self.glow_radius
+    }
+    pub fn get_glow_color(&self) -> String {
+        self.glow_color.clone()
+    }
+    pub fn get_major_line_interval(&self) -> u32 {
+        self.major_line_interval
+    }
+    pub fn get_major_line_color(&self) -> String {
+        self.major_line_color.clone()
+    }
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "web", wasm_bindgen)]
 impl UniverseConfig {
     /// Create a new UniverseBuilder with **default** parameters.
     /// 
@@ -69,18 +135,30 @@ impl UniverseConfig {
     /// ```
     pub fn new() -> Self {
         Self {
-            input: UniverseInput::RleString(String::from("this")),
+            input: UniverseInput::Random,
             padding: DEFAULT_PADDING,
             cell_size: DEFAULT_CELL_SIZE,
             override_size: None,
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            render_mode: RenderMode::Canvas,
+            sub_cell_shading: SubCellShading::Any,
             lines_enabled: true,
             line_width: 2,
             border_width: 4,
             allow_overflow: false,
+            device_pixel_ratio: 1.0,
 
             line_color: String::from("white"),
             cell_alive_color: String::from("black"),
             cell_dead_color: String::from("white"),
+            selection_color: String::from("#1e90ff"),
+            cell_shape: CellShape::Square,
+            corner_radius: 0,
+            cell_gap: 0,
+            glow_radius: 0,
+            glow_color: String::from("black"),
+            major_line_interval: 0,
+            major_line_color: String::from("gray"),
         }
     }
 
@@ -90,6 +168,60 @@ impl UniverseConfig {
         self
     }
 
+    /// Create a universe from a pattern string. The format, RLE, plaintext
+    /// (`.cells`), Life 1.05, Life 1.06 or macrocell (`.mc`), is detected from
+    /// the contents.
+    pub fn set_pattern_input(mut self, pattern: &str) -> Self {
+        self.input = UniverseInput::RleString(String::from(pattern));
+        self
+    }
+
+    /// Create a universe from a pattern in the bundled library, looked up by
+    /// name ignoring case. Fails if the library has no pattern by that name.
+    ///   e.g. name = "glider" or name = "Gosper glider gun"
+    pub fn set_named_input(mut self, name: &str) -> Result<UniverseConfig, String> {
+        if library::find(name).is_none() {
+            return Err(format!("no pattern in the library named `{}`", name));
+        }
+        self.input = UniverseInput::Named(String::from(name));
+        Ok(self)
+    }
+
+    /// Add a pattern to a composition of patterns, placing the top left cell
+    /// of the transformed pattern at column `x` and row `y`. The pattern is
+    /// first run forward `phase` generations. The composition as a whole is
+    /// centred in the universe.
+    ///   e.g. two gliders and a block to set up a collision
+    pub fn add_placement(mut self, pattern: &str, x: i32, y: i32, transform: Transform, phase: u32) -> Self {
+        let placement = composition::Placement {
+            pattern: String::from(pattern),
+            x: x as i64,
+            y: y as i64,
+            transform,
+            phase,
+        };
+        match &mut self.input {
+            UniverseInput::Composition(placements) => placements.push(placement),
+            _ => self.input = UniverseInput::Composition(vec![placement]),
+        }
+        self
+    }
+
+    /// Create a universe with a symmetric soup in the centre. The symmetry is
+    /// given by its apgsearch name, and an unknown name is an error.
+    ///   e.g. symmetry = "C1" or symmetry = "D2_+1"
+    pub fn set_soup_input(mut self, symmetry: &str) -> Result<UniverseConfig, String> {
+        self.input = UniverseInput::Soup(symmetry.parse()?, None);
+        Ok(self)
+    }
+
+    /// Create a universe with a symmetric soup that is generated from `seed`,
+    /// so the same soup is produced every time.
+    pub fn set_seeded_soup_input(mut self, symmetry: &str, seed: u64) -> Result<UniverseConfig, String> {
+        self.input = UniverseInput::Soup(symmetry.parse()?, Some(seed));
+        Ok(self)
+    }
+
     /// Add additional cells to the outside of the universe. If the absolute
     /// size is specified, the padding will added to the outside of the size.
     pub fn set_padding(mut self, padding: u32) -> Self {
@@ -97,36 +229,119 @@ impl UniverseConfig {
         self
     }
 
-    /// Set the color of the lines between cells. The color is given as string
-    /// that is passed to javascript.
-    ///   e.g. color = "red" or color = "#FF0000"
+    /// Set the color of the lines between cells. The color is a CSS color
+    /// string, passed as is to a canvas. The other renderers support the
+    /// forms listed by `Rgba::parse` and draw any other color black.
+    ///   e.g. color = "red", color = "#FF0000" or color = "rgb(255, 0, 0)"
     pub fn set_line_color(mut self, color: &str) -> Self {
         self.line_color = String::from(color);
         self
     }
 
-    /// Set the color of the cells when they are alive. The color is given as
-    /// string that is passed to javascript.
+    /// Set the color of the cells when they are alive, as for
+    /// `set_line_color`.
     ///   e.g. color = "red" or color = "#FF0000"
     pub fn set_cell_alive_color(mut self, color: &str) -> Self {
         self.cell_alive_color = String::from(color);
         self
     }
 
-    /// Set the color of the cells when they are dead. The color is given as
-    /// string that is passed to javascript.
+    /// Set the color of the cells when they are dead, as for
+    /// `set_line_color`.
     ///   e.g. color = "red" or color = "#FF0000"
     pub fn set_cell_dead_color(mut self, color: &str) -> Self {
         self.cell_dead_color = String::from(color);
         self
     }
 
+    /// Set the color of the outline drawn around the selection, as for
+    /// `set_line_color`.
+    ///   e.g. color = "red" or color = "#FF0000"
+    pub fn set_selection_color(mut self, color: &str) -> Self {
+        self.selection_color = String::from(color);
+        self
+    }
+
+    /// Set the shape of alive cells. Dead cells are always drawn as squares.
+    pub fn set_cell_shape(mut self, shape: CellShape) -> Self {
+        self.cell_shape = shape;
+        self
+    }
+
+    /// Set the radius of the corners of `Rounded` cells, in pixels.
+    pub fn set_corner_radius(mut self, radius: u32) -> Self {
+        self.corner_radius = radius;
+        self
+    }
+
+    /// Leave a gap of `gap` pixels, in the dead color, between the shape of an
+    /// alive cell and each edge of its square.
+    pub fn set_cell_gap(mut self, gap: u32) -> Self {
+        self.cell_gap = gap;
+        self
+    }
+
+    /// Surround alive cells with a glow that fades out over `radius` pixels.
+    /// The glow stays inside the square of its cell, so it needs a gap to
+    /// show. A radius of 0 turns the glow off.
+    ///   e.g. `.set_cell_gap(3).set_glow(3, "#00ff00")`
+    pub fn set_glow(mut self, radius: u32, color: &str) -> Self {
+        self.glow_radius = radius;
+        self.glow_color = String::from(color);
+        self
+    }
+
+    /// Draw every `interval`th grid line in another color, counted from the
+    /// top left of the universe. Combined with disabled lines, only these
+    /// lines are drawn. An interval of 0 turns them off.
+    ///   e.g. interval = 10 and color = "#888888"
+    pub fn set_major_lines(mut self, interval: u32, color: &str) -> Self {
+        self.major_line_interval = interval;
+        self.major_line_color = String::from(color);
+        self
+    }
+
+    /// Show or hide the grid lines between cells.
+    pub fn set_lines_enabled(mut self, enabled: bool) -> Self {
+        self.lines_enabled = enabled;
+        self
+    }
+
+    /// Set how the universe is drawn. `PixelBuffer` draws large grids much
+    /// faster than the default `Canvas`.
+    pub fn set_render_mode(mut self, mode: RenderMode) -> Self {
+        self.render_mode = mode;
+        self
+    }
+
+    /// Set how cells are shaded when zoomed out so far that several cells
+    /// share a pixel.
+    pub fn set_sub_cell_shading(mut self, shading: SubCellShading) -> Self {
+        self.sub_cell_shading = shading;
+        self
+    }
+
+    /// Set the number of device pixels per CSS pixel, so that the grid is
+    /// drawn at the full resolution of high density displays.
+    ///   e.g. ratio = window.devicePixelRatio
+    pub fn set_device_pixel_ratio(mut self, ratio: f64) -> Self {
+        self.device_pixel_ratio = ratio;
+        self
+    }
+
     /// If used, this overrides the universe size to the specified parameters.
     pub fn set_override_size(mut self, width: u32, height: u32) -> Self {
         self.override_size = Some((width, height));
         self
     }
 
+    /// Set the memory, in bytes, that may be used to remember past generations
+    /// and edits for stepping back and undo. A limit of 0 disables history.
+    pub fn set_history_limit(mut self, bytes: usize) -> Self {
+        self.history_limit = bytes;
+        self
+    }
+
     /// Construct a universe from a configuration.
     pub fn construct(self) -> universe::Universe {
         universe::Universe::from(self)
@@ -0,0 +1,155 @@
+use std::fmt;
+
+use crate::universe::Cell;
+
+/// The characters used for alive and dead cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Glyphs {
+    pub alive: char,
+    pub dead: char,
+}
+
+impl Glyphs {
+    pub const SQUARES: Glyphs = Glyphs { alive: '◼', dead: '◻' };
+    /// The characters of the plaintext format.
+    pub const ASCII: Glyphs = Glyphs { alive: 'O', dead: '.' };
+}
+
+impl Default for Glyphs {
+    fn default() -> Self {
+        Glyphs::SQUARES
+    }
+}
+
+/// Text rendering of a grid of cells, one line per row. Created by
+/// `Universe::display` or `RleData::display` and configured with its
+/// builder methods.
+///   e.g. `println!("{}", universe.display().region(0, 0, 20, 10).compact(true))`
+#[derive(Clone, Copy, Debug)]
+pub struct GridDisplay<'a> {
+    width: u32,
+    height: u32,
+    cells: &'a [Cell],
+    glyphs: Glyphs,
+    region: Option<(u32, u32, u32, u32)>,
+    compact: bool,
+}
+
+impl<'a> GridDisplay<'a> {
+    pub fn new(width: u32, height: u32, cells: &'a [Cell]) -> Self {
+        GridDisplay {
+            width,
+            height,
+            cells,
+            glyphs: Glyphs::default(),
+            region: None,
+            compact: false,
+        }
+    }
+
+    /// Set the characters used for alive and dead cells.
+    pub fn glyphs(mut self, alive: char, dead: char) -> Self {
+        self.glyphs = Glyphs { alive, dead };
+        self
+    }
+
+    /// Only show the `width` x `height` region whose top left cell is at
+    /// `(row, col)`. Any part of the region outside the grid is clipped.
+    pub fn region(mut self, row: u32, col: u32, width: u32, height: u32) -> Self {
+        self.region = Some((row, col, width, height));
+        self
+    }
+
+    /// Draw two rows per line with the half-block characters `▀`, `▄` and
+    /// `█`, so that cells are roughly square. The glyphs are not used.
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    /// The rows and columns shown, clipped to the grid.
+    fn bounds(&self) -> (std::ops::Range<u32>, std::ops::Range<u32>) {
+        let (row, col, width, height) = self.region.unwrap_or((0, 0, self.width, self.height));
+        let rows = row.min(self.height)..row.saturating_add(height).min(self.height);
+        let cols = col.min(self.width)..col.saturating_add(width).min(self.width);
+        (rows, cols)
+    }
+
+    fn is_alive(&self, row: u32, col: u32) -> bool {
+        self.cells[(row * self.width + col) as usize] == Cell::Alive
+    }
+}
+
+impl fmt::Display for GridDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (rows, cols) = self.bounds();
+        if self.compact {
+            for row in rows.clone().step_by(2) {
+                for col in cols.clone() {
+                    // the row below may lie outside the region
+                    let bottom = row + 1 < rows.end && self.is_alive(row + 1, col);
+                    let symbol = match (self.is_alive(row, col), bottom) {
+                        (false, false) => ' ',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (true, true) => '█',
+                    };
+                    write!(f, "{}", symbol)?;
+                }
+                writeln!(f)?;
+            }
+        } else {
+            for row in rows {
+                for col in cols.clone() {
+                    let symbol = if self.is_alive(row, col) { self.glyphs.alive } else { self.glyphs.dead };
+                    write!(f, "{}", symbol)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4 wide, 3 tall grid with a diagonal from the top left and the
+    /// bottom right cell alive.
+    fn cells() -> Vec<Cell> {
+        let mut cells = vec![Cell::Dead; 12];
+        for &idx in [0, 5, 10, 11].iter() {
+            cells[idx] = Cell::Alive;
+        }
+        cells
+    }
+
+    #[test]
+    fn draws_each_row_with_the_glyphs() {
+        let cells = cells();
+        let display = GridDisplay::new(4, 3, &cells);
+        assert_eq!(display.to_string(), "◼◻◻◻\n◻◼◻◻\n◻◻◼◼\n");
+        assert_eq!(display.glyphs('#', '-').to_string(), "#---\n-#--\n--##\n");
+    }
+
+    #[test]
+    fn clips_the_region_to_the_grid() {
+        let cells = cells();
+        let display = GridDisplay::new(4, 3, &cells).glyphs('O', '.');
+        assert_eq!(display.region(1, 1, 2, 2).to_string(), "O.\n.O\n");
+        assert_eq!(display.region(2, 2, 10, 10).to_string(), "OO\n");
+        assert_eq!(display.region(5, 0, 2, 2).to_string(), "");
+    }
+
+    #[test]
+    fn compact_mode_draws_two_rows_per_line() {
+        let cells = cells();
+        let display = GridDisplay::new(4, 3, &cells).glyphs('O', '.').compact(true);
+        // the odd last row is drawn as the top half of a line
+        assert_eq!(display.to_string(), "▀▄  \n  ▀▀\n");
+        // a region ending on an odd row hides the row below it
+        assert_eq!(display.region(0, 0, 4, 1).to_string(), "▀   \n");
+        assert_eq!(display.region(1, 0, 4, 2).to_string(), " ▀▄▄\n");
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::VecDeque;
+use std::mem;
+
+use crate::universe::Cell;
+
+/// Every `KEYFRAME_INTERVAL`th generation is stored in full rather than as a
+/// delta, which bounds the work needed to reconstruct any single frame.
+const KEYFRAME_INTERVAL: u32 = 32;
+
+/// A grid of flags encoded as alternating run lengths, starting with a run of
+/// unset flags. A keyframe sets the flag for alive cells, a delta sets it for
+/// cells that changed (i.e. the XOR of two grids).
+#[derive(Clone, Debug)]
+struct Runs(Vec<u32>);
+
+impl Runs {
+    fn encode<I: Iterator<Item = bool>>(flags: I) -> Self {
+        let mut runs = Vec::new();
+        let mut current = false;
+        let mut length = 0;
+        for flag in flags {
+            if flag != current {
+                runs.push(length);
+                current = flag;
+                length = 0;
+            }
+            length += 1;
+        }
+        runs.push(length);
+        Runs(runs)
+    }
+
+    fn keyframe(cells: &[Cell]) -> Self {
+        Runs::encode(cells.iter().map(|&cell| cell == Cell::Alive))
+    }
+
+    fn delta(from: &[Cell], to: &[Cell]) -> Self {
+        Runs::encode(from.iter().zip(to.iter()).map(|(a, b)| a != b))
+    }
+
+    /// Toggle every cell covered by a set run.
+    fn xor_into(&self, cells: &mut [Cell]) {
+        let mut idx = 0;
+        for (i, &length) in self.0.iter().enumerate() {
+            let length = length as usize;
+            if i % 2 == 1 {
+                for cell in &mut cells[idx..idx + length] {
+                    *cell = match *cell {
+                        Cell::Dead => Cell::Alive,
+                        Cell::Alive => Cell::Dead,
+                    };
+                }
+            }
+            idx += length;
+        }
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.0.len() * mem::size_of::<u32>()
+    }
+}
+
+/// The cells toggled by a user edit. Edits of a few cells, such as drawing
+/// with the pointer, list the cells; larger edits store a delta of the grid.
+#[derive(Clone, Debug)]
+enum Edit {
+    Runs(Runs),
+    Cells(Vec<usize>),
+}
+
+impl Edit {
+    fn xor_into(&self, cells: &mut [Cell]) {
+        match self {
+            Edit::Runs(runs) => runs.xor_into(cells),
+            Edit::Cells(indices) => {
+                for &idx in indices {
+                    cells[idx] = match cells[idx] {
+                        Cell::Dead => Cell::Alive,
+                        Cell::Alive => Cell::Dead,
+                    };
+                }
+            },
+        }
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        match self {
+            Edit::Runs(runs) => runs.size_in_bytes(),
+            Edit::Cells(indices) => indices.len() * mem::size_of::<usize>(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    generation: u32,
+    keyframe: bool,
+    runs: Runs,
+}
+
+/// A bounded record of past generations and of user edits.
+///
+/// Generations are pushed before each tick and can be restored afterwards.
+/// Edits are kept as deltas on the current generation so they can be undone
+/// and redone; they are forgotten once the generation changes. Once the
+/// stored data, counting the full copy kept of the newest generation, exceeds
+/// `limit` bytes the oldest generations are discarded first, then the oldest
+/// edits.
+pub struct History {
+    limit: usize,
+    size: usize,
+    frames: VecDeque<Frame>,
+    /// The cells of the newest frame, which the next delta is taken against.
+    newest: Vec<Cell>,
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    /// Create an empty history holding at most `limit` bytes. A limit of zero
+    /// disables the history.
+    pub fn new(limit: usize) -> Self {
+        History {
+            limit,
+            size: 0,
+            frames: VecDeque::new(),
+            newest: vec![],
+            undo: VecDeque::new(),
+            redo: vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.newest.clear();
+        self.undo.clear();
+        self.redo.clear();
+        self.size = 0;
+    }
+
+    /// The oldest generation that can still be restored.
+    pub fn oldest_generation(&self) -> Option<u32> {
+        self.frames.front().map(|frame| frame.generation)
+    }
+
+    /// Record the cells of `generation`, which must directly follow the
+    /// newest recorded generation (or start a new history).
+    pub fn push(&mut self, generation: u32, cells: &[Cell]) {
+        if self.limit == 0 {
+            return;
+        }
+        // edits only apply to the generation that is being left
+        self.clear_edits();
+        let follows = self.frames.back().is_some_and(|frame| frame.generation + 1 == generation);
+        if !follows {
+            self.frames.clear();
+            self.size = 0;
+        }
+
+        let keyframe = self.frames.is_empty()
+            || generation % KEYFRAME_INTERVAL == 0
+            || self.newest.len() != cells.len();
+        let runs = if keyframe {
+            Runs::keyframe(cells)
+        } else {
+            Runs::delta(&self.newest, cells)
+        };
+        self.size += runs.size_in_bytes();
+        self.frames.push_back(Frame { generation, keyframe, runs });
+        self.newest = cells.to_vec();
+        self.evict();
+    }
+
+    /// Remove `generation` and every newer frame, and return the cells of
+    /// `generation`. Returns `None`, leaving the history untouched, if
+    /// `generation` is not recorded.
+    pub fn rewind_to(&mut self, generation: u32) -> Option<Vec<Cell>> {
+        let oldest = self.oldest_generation()?;
+        let newest = self.frames.back()?.generation;
+        if generation < oldest || generation > newest {
+            return None;
+        }
+        self.clear_edits();
+        self.frames.truncate((generation - oldest + 1) as usize);
+        self.size = self.frames.iter().map(|frame| frame.runs.size_in_bytes()).sum();
+        self.newest = self.reconstruct(self.frames.len() - 1);
+        self.pop().map(|(_, cells)| cells)
+    }
+
+    /// Remove and return the newest frame as `(generation, cells)`.
+    pub fn pop(&mut self) -> Option<(u32, Vec<Cell>)> {
+        let frame = self.frames.pop_back()?;
+        self.size -= frame.runs.size_in_bytes();
+        let cells = mem::take(&mut self.newest);
+        if !self.frames.is_empty() {
+            self.newest = if frame.keyframe {
+                self.reconstruct(self.frames.len() - 1)
+            } else {
+                let mut previous = cells.clone();
+                frame.runs.xor_into(&mut previous);
+                previous
+            };
+        }
+        self.clear_edits();
+        Some((frame.generation, cells))
+    }
+
+    /// Record a user edit of the current generation, clearing anything that
+    /// could be redone.
+    pub fn record_edit(&mut self, before: &[Cell], after: &[Cell]) {
+        if self.limit == 0 || before.len() != after.len() || before == after {
+            return;
+        }
+        self.push_edit(Edit::Runs(Runs::delta(before, after)));
+    }
+
+    /// Record a user edit of the current generation that toggled the cells
+    /// at `changed`, clearing anything that could be redone.
+    pub fn record_cells(&mut self, changed: &[usize]) {
+        if self.limit == 0 || changed.is_empty() {
+            return;
+        }
+        self.push_edit(Edit::Cells(changed.to_vec()));
+    }
+
+    fn push_edit(&mut self, edit: Edit) {
+        self.size += edit.size_in_bytes();
+        self.size -= self.redo.drain(..).map(|edit| edit.size_in_bytes()).sum::<usize>();
+        self.undo.push_back(edit);
+        self.evict();
+    }
+
+    /// Revert the latest edit on `cells`. Returns whether there was one.
+    pub fn undo(&mut self, cells: &mut [Cell]) -> bool {
+        match self.undo.pop_back() {
+            Some(edit) => {
+                edit.xor_into(cells);
+                self.redo.push(edit);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Reapply the latest undone edit on `cells`. Returns whether there was
+    /// one.
+    pub fn redo(&mut self, cells: &mut [Cell]) -> bool {
+        match self.redo.pop() {
+            Some(edit) => {
+                edit.xor_into(cells);
+                self.undo.push_back(edit);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Forget all edits, e.g. after the generation has changed.
+    pub fn clear_edits(&mut self) {
+        self.size -= self.undo.drain(..).map(|edit| edit.size_in_bytes()).sum::<usize>();
+        self.size -= self.redo.drain(..).map(|edit| edit.size_in_bytes()).sum::<usize>();
+    }
+
+    /// Rebuild the cells of the frame at `index` from the nearest keyframe.
+    fn reconstruct(&self, index: usize) -> Vec<Cell> {
+        let start = (0..=index)
+            .rev()
+            .find(|&i| self.frames[i].keyframe)
+            .expect("history has no keyframe");
+        let mut cells = vec![Cell::Dead; self.frame_len(start)];
+        self.frames[start].runs.xor_into(&mut cells);
+        for frame in self.frames.range(start + 1..=index) {
+            frame.runs.xor_into(&mut cells);
+        }
+        cells
+    }
+
+    /// The number of cells covered by a frame.
+    fn frame_len(&self, index: usize) -> usize {
+        self.frames[index].runs.0.iter().map(|&length| length as usize).sum()
+    }
+
+    /// The bytes held, including the cells of the newest frame.
+    fn total_size(&self) -> usize {
+        self.size + self.newest.len() * mem::size_of::<Cell>()
+    }
+
+    /// Drop the oldest frames, then the oldest edits, until within the limit.
+    /// The newest frame is always kept.
+    fn evict(&mut self) {
+        while self.total_size() > self.limit && self.frames.len() > 1 {
+            if !self.frames[1].keyframe {
+                let cells = self.reconstruct(1);
+                let runs = Runs::keyframe(&cells);
+                self.size -= self.frames[1].runs.size_in_bytes();
+                self.size += runs.size_in_bytes();
+                self.frames[1].runs = runs;
+                self.frames[1].keyframe = true;
+            }
+            let frame = self.frames.pop_front().unwrap();
+            self.size -= frame.runs.size_in_bytes();
+        }
+        while self.total_size() > self.limit {
+            match self.undo.pop_front() {
+                Some(edit) => self.size -= edit.size_in_bytes(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(alive: &[usize]) -> Vec<Cell> {
+        let mut cells = vec![Cell::Dead; 16];
+        for &idx in alive {
+            cells[idx] = Cell::Alive;
+        }
+        cells
+    }
+
+    #[test]
+    fn pop_returns_frames_newest_first() {
+        let mut history = History::new(1 << 20);
+        for generation in 0..40 {
+            history.push(generation, &grid(&[generation as usize % 16]));
+        }
+        for generation in (0..40).rev() {
+            assert_eq!(history.pop(), Some((generation, grid(&[generation as usize % 16]))));
+        }
+        assert_eq!(history.pop(), None);
+        assert_eq!(history.size, 0);
+    }
+
+    #[test]
+    fn push_restarts_after_a_gap() {
+        let mut history = History::new(1 << 20);
+        history.push(0, &grid(&[0]));
+        history.push(1, &grid(&[1]));
+        history.push(5, &grid(&[5]));
+        assert_eq!(history.oldest_generation(), Some(5));
+        assert_eq!(history.pop(), Some((5, grid(&[5]))));
+        assert_eq!(history.pop(), None);
+    }
+
+    #[test]
+    fn rewind_to_restores_a_generation() {
+        let mut history = History::new(1 << 20);
+        for generation in 0..10 {
+            history.push(generation, &grid(&[generation as usize]));
+        }
+        assert_eq!(history.rewind_to(20), None);
+        assert_eq!(history.rewind_to(4), Some(grid(&[4])));
+        assert_eq!(history.pop(), Some((3, grid(&[3]))));
+    }
+
+    #[test]
+    fn undo_and_redo_edits() {
+        let mut history = History::new(1 << 20);
+        let before = grid(&[1]);
+        let mut cells = grid(&[1, 2]);
+        history.record_edit(&before, &cells);
+        assert!(history.undo(&mut cells));
+        assert_eq!(cells, before);
+        assert!(!history.undo(&mut cells));
+        assert!(history.redo(&mut cells));
+        assert_eq!(cells, grid(&[1, 2]));
+        assert!(!history.redo(&mut cells));
+    }
+
+    #[test]
+    fn undo_and_redo_cell_edits() {
+        let mut history = History::new(1 << 20);
+        let mut cells = grid(&[1, 2]);
+        history.record_edit(&grid(&[1]), &cells);
+        cells = grid(&[2, 5]);
+        history.record_cells(&[1, 5]);
+        assert_eq!(history.size, Runs::delta(&grid(&[1]), &grid(&[1, 2])).size_in_bytes() + 2 * mem::size_of::<usize>());
+        assert!(history.undo(&mut cells));
+        assert_eq!(cells, grid(&[1, 2]));
+        assert!(history.undo(&mut cells));
+        assert_eq!(cells, grid(&[1]));
+        assert!(history.redo(&mut cells));
+        assert!(history.redo(&mut cells));
+        assert_eq!(cells, grid(&[2, 5]));
+        history.clear_edits();
+        assert_eq!(history.size, 0);
+    }
+
+    #[test]
+    fn edits_are_forgotten_by_push() {
+        let mut history = History::new(1 << 20);
+        let mut cells = grid(&[1, 2]);
+        history.record_edit(&grid(&[1]), &cells);
+        history.push(0, &cells);
+        assert!(!history.undo(&mut cells));
+    }
+
+    #[test]
+    fn size_counts_undone_edits() {
+        let mut history = History::new(1 << 20);
+        let mut cells = grid(&[1, 2]);
+        history.record_edit(&grid(&[1]), &cells);
+        history.undo(&mut cells);
+        history.push(0, &cells);
+        history.push(1, &cells);
+        assert_eq!(history.pop(), Some((1, cells.clone())));
+        assert_eq!(history.pop(), Some((0, cells)));
+        assert_eq!(history.size, 0);
+    }
+
+    #[test]
+    fn evict_drops_the_oldest_frames() {
+        let keyframe_size = Runs::keyframe(&grid(&[3])).size_in_bytes();
+        let mut history = History::new(4 * keyframe_size);
+        for generation in 0..20 {
+            history.push(generation, &grid(&[generation as usize % 16]));
+        }
+        assert!(history.total_size() <= history.limit);
+        assert_eq!(history.frames.back().map(|frame| frame.generation), Some(19));
+        let oldest = history.oldest_generation().unwrap();
+        assert!(oldest > 0);
+        assert_eq!(history.rewind_to(oldest), Some(grid(&[oldest as usize % 16])));
+    }
+}
@@ -1,6 +1,20 @@
 #[macro_use]
-mod utils;
-mod rle_loader;
+pub mod utils;
+pub mod rle_loader;
+pub mod pattern;
+pub mod display;
+pub mod render;
+pub mod transform;
+pub mod composition;
+pub mod selection;
+pub mod library;
+pub mod plaintext;
+pub mod life105;
+pub mod life106;
+pub mod macrocell;
+mod history;
+mod snapshot;
+pub mod soup;
 pub mod config;
 pub mod universe;
 
@@ -0,0 +1,220 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "web")]
+use wasm_bindgen::prelude::*;
+
+use crate::pattern::Pattern;
+use crate::rle_loader;
+
+/// The kind of a pattern in the library.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Category {
+    StillLife,
+    Oscillator,
+    Spaceship,
+    Gun,
+    Methuselah,
+    /// Several patterns arranged together.
+    Collection,
+}
+
+impl Category {
+    pub const ALL: [Category; 6] = [
+        Category::StillLife,
+        Category::Oscillator,
+        Category::Spaceship,
+        Category::Gun,
+        Category::Methuselah,
+        Category::Collection,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::StillLife => "still life",
+            Category::Oscillator => "oscillator",
+            Category::Spaceship => "spaceship",
+            Category::Gun => "gun",
+            Category::Methuselah => "methuselah",
+            Category::Collection => "collection",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A pattern compiled into the library.
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    pub name: &'static str,
+    pub category: Category,
+    /// The pattern in the RLE format.
+    pub rle: &'static str,
+}
+
+impl Entry {
+    pub fn pattern(&self) -> Pattern {
+        rle_loader::load_pattern(self.rle).expect("library patterns are valid RLE")
+    }
+}
+
+macro_rules! entry {
+    ($name:expr, $category:ident, $rle:expr) => {
+        Entry { name: $name, category: Category::$category, rle: $rle }
+    };
+}
+
+/// Every pattern in the library, grouped by category.
+pub const PATTERNS: &[Entry] = &[
+    entry!("block", StillLife, "x = 2, y = 2\n2o$2o!"),
+    entry!("beehive", StillLife, "x = 4, y = 3\nb2o$o2bo$b2o!"),
+    entry!("loaf", StillLife, "x = 4, y = 4\nb2o$o2bo$bobo$2bo!"),
+    entry!("boat", StillLife, "x = 3, y = 3\n2o$obo$bo!"),
+    entry!("ship", StillLife, "x = 3, y = 3\n2o$obo$b2o!"),
+    entry!("tub", StillLife, "x = 3, y = 3\nbo$obo$bo!"),
+    entry!("barge", StillLife, "x = 4, y = 4\nbo$obo$bobo$2bo!"),
+    entry!("long boat", StillLife, "x = 4, y = 4\n2o$obo$bobo$2bo!"),
+    entry!("pond", StillLife, "x = 4, y = 4\nb2o$o2bo$o2bo$b2o!"),
+    entry!("snake", StillLife, "x = 4, y = 2\n2obo$ob2o!"),
+    entry!("aircraft carrier", StillLife, "x = 4, y = 3\n2o$o2bo$2b2o!"),
+    entry!("eater 1", StillLife, "x = 4, y = 4\n2o$obo$2bo$2b2o!"),
+    entry!("blinker", Oscillator, "x = 3, y = 1\n3o!"),
+    entry!("toad", Oscillator, "x = 4, y = 2\nb3o$3o!"),
+    entry!("beacon", Oscillator, "x = 4, y = 4\n2o$o$3bo$2b2o!"),
+    entry!("clock", Oscillator, "x = 4, y = 4\n2bo$obo$bobo$bo!"),
+    entry!("pulsar", Oscillator, "x = 13, y = 13\n2b3o3b3o2$o4bobo4bo$o4bobo4bo$o4bobo4bo$2b3o3b3o2$2b3o3b3o$o4bobo4bo$o4bobo4bo$o4bobo4bo2$2b3o3b3o!"),
+    entry!("octagon 2", Oscillator, "x = 8, y = 8\n3b2o$2bo2bo$bo4bo$o6bo$o6bo$bo4bo$2bo2bo$3b2o!"),
+    entry!("figure eight", Oscillator, "x = 6, y = 6\n2o$2obo$4bo$bo$2bob2o$4b2o!"),
+    entry!("tumbler", Oscillator, "x = 9, y = 5\nbo5bo$obo3bobo$o2bobo2bo$2bo3bo$2b2ob2o!"),
+    entry!("pentadecathlon", Oscillator, "x = 10, y = 3\n2bo4bo$2ob4ob2o$2bo4bo!"),
+    entry!("queen bee shuttle", Oscillator, "x = 22, y = 7\n9bo$7bobo$6bobo$2o3bo2bo11b2o$2o4bobo11b2o$7bobo$9bo!"),
+    entry!("glider", Spaceship, "x = 3, y = 3\nbo$2bo$3o!"),
+    entry!("lwss", Spaceship, "x = 5, y = 4\nbo2bo$o$o3bo$4o!"),
+    entry!("mwss", Spaceship, "x = 6, y = 5\n3bo$bo3bo$o$o4bo$5o!"),
+    entry!("hwss", Spaceship, "x = 7, y = 5\n3b2o$bo4bo$o$o5bo$6o!"),
+    entry!("gosper glider gun", Gun, "x = 36, y = 9\n24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!"),
+    entry!("r-pentomino", Methuselah, "x = 3, y = 3\nb2o$2o$bo!"),
+    entry!("acorn", Methuselah, "x = 7, y = 3\nbo$3bo$2o2b3o!"),
+    entry!("diehard", Methuselah, "x = 8, y = 3\n6bo$2o$bo3b3o!"),
+    entry!("b-heptomino", Methuselah, "x = 4, y = 3\nob2o$3o$bo!"),
+    entry!("pi-heptomino", Methuselah, "x = 3, y = 3\n3o$obo$obo!"),
+    entry!("spaceships", Collection, include_str!("../spaceships.rle")),
+];
+
+/// Find a pattern by name, ignoring case.
+pub fn find(name: &str) -> Option<&'static Entry> {
+    PATTERNS.iter().find(|entry| entry.name.eq_ignore_ascii_case(name))
+}
+
+/// Load a pattern by name, ignoring case.
+pub fn load(name: &str) -> Option<Pattern> {
+    find(name).map(Entry::pattern)
+}
+
+/// The names of every pattern in the library.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+pub fn pattern_names() -> Vec<String> {
+    PATTERNS.iter().map(|entry| String::from(entry.name)).collect()
+}
+
+/// The names of the patterns in a category.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+pub fn pattern_names_in(category: Category) -> Vec<String> {
+    PATTERNS
+        .iter()
+        .filter(|entry| entry.category == category)
+        .map(|entry| String::from(entry.name))
+        .collect()
+}
+
+/// The category of a pattern, if the library has a pattern by that name.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+pub fn pattern_category(name: &str) -> Option<Category> {
+    find(name).map(|entry| entry.category)
+}
+
+/// The RLE source of a pattern, if the library has a pattern by that name.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+pub fn pattern_rle(name: &str) -> Option<String> {
+    find(name).map(|entry| String::from(entry.rle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coordinates(pattern: &Pattern) -> Vec<(i64, i64)> {
+        pattern.cells().iter().map(|&(x, y)| (x as i64, y as i64)).collect()
+    }
+
+    /// Run alive cells, given by their coordinates, forward one generation.
+    fn step(cells: &[(i64, i64)]) -> Vec<(i64, i64)> {
+        if cells.is_empty() {
+            return Vec::new();
+        }
+        let min_x = cells.iter().map(|&(x, _)| x).min().unwrap();
+        let min_y = cells.iter().map(|&(_, y)| y).min().unwrap();
+        Pattern::from_coordinates(cells)
+            .advanced(1)
+            .cells()
+            .iter()
+            .map(|&(x, y)| (x as i64 + min_x - 1, y as i64 + min_y - 1))
+            .collect()
+    }
+
+    /// The first generation, up to `max`, at which the pattern has the same
+    /// shape as at the start, and how far it has moved by then.
+    fn period(pattern: &Pattern, max: u32) -> Option<(u32, i64, i64)> {
+        let start = coordinates(pattern);
+        let mut cells = start.clone();
+        for generation in 1..=max {
+            cells = step(&cells);
+            if cells.len() == start.len() && !cells.is_empty() {
+                let (dx, dy) = (cells[0].0 - start[0].0, cells[0].1 - start[0].1);
+                if cells.iter().zip(start.iter()).all(|(&(x, y), &(sx, sy))| (x - sx, y - sy) == (dx, dy)) {
+                    return Some((generation, dx, dy));
+                }
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn patterns_match_their_size_and_category() {
+        for entry in PATTERNS {
+            let pattern = rle_loader::load_pattern(entry.rle).unwrap_or_else(|err| panic!("{}: {}", entry.name, err));
+            // the header gives the bounding box of the alive cells
+            assert_eq!(pattern.trimmed(), pattern, "{} has empty margins", entry.name);
+            let period = period(&pattern, 30);
+            match entry.category {
+                Category::StillLife => assert_eq!(period, Some((1, 0, 0)), "{}", entry.name),
+                Category::Oscillator => {
+                    assert!(matches!(period, Some((period, 0, 0)) if period > 1), "{}: {:?}", entry.name, period)
+                },
+                Category::Spaceship => {
+                    assert!(matches!(period, Some((_, dx, dy)) if (dx, dy) != (0, 0)), "{}: {:?}", entry.name, period)
+                },
+                Category::Gun => {
+                    // a gun of period 30 or less has fired at least three
+                    // gliders by then
+                    let end = (0..120).fold(coordinates(&pattern), |cells, _| step(&cells));
+                    assert!(end.len() >= pattern.population() + 15, "{} does not grow", entry.name);
+                },
+                Category::Methuselah => assert_eq!(period, None, "{}", entry.name),
+                Category::Collection => assert!(pattern.population() > 0, "{}", entry.name),
+            }
+        }
+    }
+
+    #[test]
+    fn finds_patterns_ignoring_case() {
+        assert_eq!(find("Gosper Glider Gun").map(|entry| entry.category), Some(Category::Gun));
+        assert_eq!(load("GLIDER").map(|pattern| pattern.population()), Some(5));
+        assert!(load("no such pattern").is_none());
+    }
+}
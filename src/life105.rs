@@ -0,0 +1,121 @@
+use crate::pattern::Pattern;
+
+/// The header line that starts every Life 1.05 file.
+pub const HEADER: &str = "#Life 1.05";
+
+/// Lines in a Life 1.05 file should be no longer than this, so wide patterns
+/// are written as several blocks.
+const MAX_LINE_LENGTH: u32 = 80;
+
+/// Load a pattern in the Life 1.05 format. The pattern is made of blocks,
+/// each starting with a `#P x y` line giving the position of its top left
+/// cell, followed by rows of `.` (dead) and `*` (alive) cells. Other `#`
+/// lines (description, rule) are ignored.
+pub fn load(text: &str) -> Result<Pattern, String> {
+    let mut coordinates = Vec::new();
+    let mut x = 0;
+    let mut y = 0;
+
+    for line in text.lines().map(|line| line.trim()) {
+        if let Some(position) = line.strip_prefix("#P") {
+            let mut numbers = position.split_whitespace().map(|number| {
+                number.parse::<i64>().map_err(|_| format!("error parsing block position (`{}`)", number))
+            });
+            match (numbers.next(), numbers.next()) {
+                (Some(block_x), Some(block_y)) => {
+                    x = block_x?;
+                    y = block_y?;
+                },
+                _ => return Err(format!("expected `#P x y`, found `{}`", line)),
+            }
+        } else if line.starts_with('#') {
+            continue;
+        } else {
+            for (col, c) in line.chars().enumerate() {
+                match c {
+                    '.' => (),
+                    '*' | 'O' => coordinates.push((x + col as i64, y)),
+                    _ => return Err(format!("invalid Life 1.05 character `{}`", c)),
+                }
+            }
+            y += 1;
+        }
+    }
+
+    Ok(Pattern::from_coordinates(&coordinates))
+}
+
+/// Write a pattern in the Life 1.05 format, centred on the origin. Patterns
+/// wider than a line are split into several blocks.
+pub fn save(pattern: &Pattern) -> String {
+    let mut text = String::from(HEADER);
+    text.push('\n');
+    let x_offset = -(pattern.width() as i64 / 2);
+    let y_offset = -(pattern.height() as i64 / 2);
+
+    let mut block_start = 0;
+    while block_start < pattern.width() {
+        let block_end = (block_start + MAX_LINE_LENGTH).min(pattern.width());
+        let cells = pattern
+            .cells()
+            .iter()
+            .filter(|&&(x, _)| block_start <= x && x < block_end)
+            .collect::<Vec<&(u32, u32)>>();
+
+        // the block starts at its first non-empty row
+        if let Some(&&(_, first_row)) = cells.first() {
+            text.push_str(&format!("#P {} {}\n", x_offset + block_start as i64, y_offset + first_row as i64));
+            let mut row = first_row;
+            let mut col = block_start;
+            for &&(x, y) in &cells {
+                while row < y {
+                    text.push('\n');
+                    row += 1;
+                    col = block_start;
+                }
+                text.extend((col..x).map(|_| '.'));
+                text.push('*');
+                col = x + 1;
+            }
+            text.push('\n');
+        }
+        block_start = block_end;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_blocks_at_their_positions() {
+        let pattern = load("#Life 1.05\n#D A glider\n#N\n#P -1 -1\n.*\n..*\n#P 4 1\n***\n").unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (7, 3));
+        assert_eq!(pattern.cells(), &[(0, 0), (1, 1), (4, 2), (5, 2), (6, 2)]);
+    }
+
+    #[test]
+    fn save_round_trips() {
+        let pattern = Pattern::new(3, 3, vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(load(&save(&pattern)), Ok(pattern));
+    }
+
+    #[test]
+    fn wide_patterns_are_saved_in_several_blocks() {
+        // a line of 200 cells, with a gap, needs three blocks of at most 80
+        let cells = (0..200).filter(|&x| x != 100).map(|x| (x, x % 2)).collect();
+        let pattern = Pattern::new(200, 2, cells);
+        let text = save(&pattern);
+        assert_eq!(text.lines().filter(|line| line.starts_with("#P")).count(), 3);
+        assert!(text.lines().all(|line| line.len() <= MAX_LINE_LENGTH as usize));
+        assert_eq!(load(&text), Ok(pattern));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(load("#Life 1.05\n#P 1\n*\n"), Err(String::from("expected `#P x y`, found `#P 1`")));
+        assert_eq!(load("#Life 1.05\n#P a 0\n*\n"), Err(String::from("error parsing block position (`a`)")));
+        assert_eq!(load("#Life 1.05\n#P 0 0\n*x\n"), Err(String::from("invalid Life 1.05 character `x`")));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::pattern::Pattern;
+
+/// The header line that starts every Life 1.06 file.
+pub const HEADER: &str = "#Life 1.06";
+
+/// Load a pattern in the Life 1.06 format: a `#Life 1.06` header followed by
+/// one `x y` coordinate pair per alive cell.
+pub fn load(text: &str) -> Result<Pattern, String> {
+    let coordinates = text
+        .lines()
+        .map(|line| line.trim())
+        // remove the header and other comment lines
+        .filter(|line| !line.starts_with('#'))
+        // remove empty lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut numbers = line.split_whitespace().map(|number| {
+                number.parse::<i64>().map_err(|_| format!("error parsing coordinate (`{}`)", number))
+            });
+            match (numbers.next(), numbers.next(), numbers.next()) {
+                (Some(x), Some(y), None) => Ok((x?, y?)),
+                _ => Err(format!("expected `x y` coordinates, found `{}`", line)),
+            }
+        })
+        .collect::<Result<Vec<(i64, i64)>, String>>()?;
+
+    Ok(Pattern::from_coordinates(&coordinates))
+}
+
+/// Write a pattern in the Life 1.06 format, with the top left corner of the
+/// pattern at the origin.
+pub fn save(pattern: &Pattern) -> String {
+    let mut text = String::from(HEADER);
+    text.push('\n');
+    for &(x, y) in pattern.cells() {
+        text.push_str(&format!("{} {}\n", x, y));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_negative_coordinates() {
+        let pattern = load("#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n").unwrap();
+        assert_eq!(pattern, Pattern::new(3, 3, vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]));
+    }
+
+    #[test]
+    fn save_round_trips() {
+        let pattern = Pattern::new(4, 2, vec![(0, 0), (3, 0), (1, 1)]);
+        let text = save(&pattern);
+        assert_eq!(text, "#Life 1.06\n0 0\n3 0\n1 1\n");
+        assert_eq!(load(&text), Ok(pattern));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(load("#Life 1.06\n1\n"), Err(String::from("expected `x y` coordinates, found `1`")));
+        assert_eq!(load("#Life 1.06\n1 2 3\n"), Err(String::from("expected `x y` coordinates, found `1 2 3`")));
+        assert_eq!(load("#Life 1.06\n1 b\n"), Err(String::from("error parsing coordinate (`b`)")));
+    }
+}
@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+
+use crate::pattern::Pattern;
+use crate::rle_loader::RleData;
+use crate::universe::Cell;
+
+/// The header line that starts every macrocell file.
+pub const HEADER: &str = "[M2]";
+
+/// The level of the leaf nodes, which are 8x8 blocks of cells.
+const LEAF_LEVEL: u32 = 3;
+const LEAF_SIZE: i64 = 1 << LEAF_LEVEL;
+/// The highest node level, so that coordinates within the root fit in an
+/// `i64`.
+const MAX_LEVEL: u32 = 62;
+
+/// A node of the quadtree. Index 0 of `Macrocell::nodes` is reserved for the
+/// empty node of any level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Node {
+    /// An 8x8 block of cells, with the cell at `(col, row)` in bit
+    /// `row * 8 + col`.
+    Leaf(u64),
+    /// A `2^level` square split into its north west, north east, south west
+    /// and south east quadrants.
+    Branch { level: u32, children: [u32; 4] },
+}
+
+/// A pattern stored as a hashed quadtree, as read from or written to a Golly
+/// macrocell (`.mc`) file. Identical subtrees are shared, so huge but regular
+/// patterns take little memory.
+///
+/// Coordinates follow Golly: the root spans `-2^(level-1)..2^(level-1)` on
+/// both axes.
+pub struct Macrocell {
+    /// The rule from the `#R` line, if any.
+    pub rule: Option<String>,
+    /// The generation from the `#G` line, if any.
+    pub generation: u64,
+    nodes: Vec<Node>,
+    root: u32,
+    level: u32,
+}
+
+impl Macrocell {
+    fn empty() -> Self {
+        Macrocell {
+            rule: None,
+            generation: 0,
+            nodes: vec![Node::Leaf(0)],
+            root: 0,
+            level: LEAF_LEVEL,
+        }
+    }
+
+    /// The level of the root node; the quadtree covers `2^level` cells on
+    /// each side.
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    fn node_level(&self, idx: u32) -> u32 {
+        match self.nodes[idx as usize] {
+            Node::Leaf(_) => LEAF_LEVEL,
+            Node::Branch { level, .. } => level,
+        }
+    }
+
+    /// Add a node, sharing an existing identical node if there is one.
+    fn intern(&mut self, node: Node, index: &mut HashMap<Node, u32>) -> u32 {
+        match node {
+            Node::Leaf(0) | Node::Branch { children: [0, 0, 0, 0], .. } => return 0,
+            _ => (),
+        }
+        let nodes = &mut self.nodes;
+        *index.entry(node).or_insert_with(|| {
+            nodes.push(node);
+            (nodes.len() - 1) as u32
+        })
+    }
+
+    /// Build a quadtree from a pattern. The top left cell of the pattern is
+    /// placed at the top left of the root.
+    pub fn from_pattern(pattern: &Pattern) -> Self {
+        let mut macrocell = Macrocell::empty();
+        let mut level = LEAF_LEVEL;
+        while (1i64 << level) < pattern.width().max(pattern.height()) as i64 {
+            level += 1;
+        }
+        let mut index = HashMap::new();
+        let cells = pattern.cells().iter().map(|&(x, y)| (x as i64, y as i64)).collect::<Vec<(i64, i64)>>();
+        macrocell.root = macrocell.build(&cells, level, 0, 0, &mut index);
+        macrocell.level = level;
+        macrocell
+    }
+
+    /// Build the node of `level` whose top left corner is at `(x, y)`, from
+    /// the alive cells that lie within it.
+    fn build(&mut self, cells: &[(i64, i64)], level: u32, x: i64, y: i64, index: &mut HashMap<Node, u32>) -> u32 {
+        if cells.is_empty() {
+            return 0;
+        }
+        if level == LEAF_LEVEL {
+            let bits = cells
+                .iter()
+                .fold(0u64, |bits, &(cx, cy)| bits | 1 << ((cy - y) * LEAF_SIZE + (cx - x)));
+            return self.intern(Node::Leaf(bits), index);
+        }
+        let half = 1i64 << (level - 1);
+        let mut quadrants: [Vec<(i64, i64)>; 4] = Default::default();
+        for &(cx, cy) in cells {
+            let quadrant = (cx >= x + half) as usize + 2 * (cy >= y + half) as usize;
+            quadrants[quadrant].push((cx, cy));
+        }
+        let children = [
+            self.build(&quadrants[0], level - 1, x, y, index),
+            self.build(&quadrants[1], level - 1, x + half, y, index),
+            self.build(&quadrants[2], level - 1, x, y + half, index),
+            self.build(&quadrants[3], level - 1, x + half, y + half, index),
+        ];
+        self.intern(Node::Branch { level, children }, index)
+    }
+
+    /// The number of alive cells.
+    pub fn population(&self) -> u64 {
+        let mut memo = HashMap::new();
+        self.node_population(self.root, &mut memo)
+    }
+
+    fn node_population(&self, idx: u32, memo: &mut HashMap<u32, u64>) -> u64 {
+        if idx == 0 {
+            return 0;
+        }
+        if let Some(&population) = memo.get(&idx) {
+            return population;
+        }
+        let population = match self.nodes[idx as usize] {
+            Node::Leaf(bits) => bits.count_ones() as u64,
+            Node::Branch { children, .. } => children
+                .iter()
+                .map(|&child| self.node_population(child, memo))
+                .fold(0u64, |total, population| total.saturating_add(population)),
+        };
+        memo.insert(idx, population);
+        population
+    }
+
+    /// The bounding box of the alive cells as `(x, y, width, height)`, or
+    /// `None` if there are none.
+    pub fn bounding_box(&self) -> Option<(i64, i64, u64, u64)> {
+        let mut memo = HashMap::new();
+        let origin = -(1i64 << (self.level - 1));
+        self.node_bounds(self.root, &mut memo).map(|(min_x, min_y, max_x, max_y)| {
+            (origin + min_x, origin + min_y, (max_x - min_x + 1) as u64, (max_y - min_y + 1) as u64)
+        })
+    }
+
+    /// The bounds `(min_x, min_y, max_x, max_y)` of the alive cells in a
+    /// node, relative to its top left corner.
+    fn node_bounds(&self, idx: u32, memo: &mut HashMap<u32, Option<(i64, i64, i64, i64)>>) -> Option<(i64, i64, i64, i64)> {
+        if idx == 0 {
+            return None;
+        }
+        if let Some(&bounds) = memo.get(&idx) {
+            return bounds;
+        }
+        let bounds = match self.nodes[idx as usize] {
+            Node::Leaf(bits) => (0..64)
+                .filter(|bit| bits >> bit & 1 == 1)
+                .map(|bit| (bit % LEAF_SIZE, bit / LEAF_SIZE))
+                .fold(None, |bounds, (x, y)| merge_bounds(bounds, Some((x, y, x, y)))),
+            Node::Branch { level, children } => {
+                let half = 1i64 << (level - 1);
+                let offsets = [(0, 0), (half, 0), (0, half), (half, half)];
+                children.iter().zip(offsets.iter()).fold(None, |bounds, (&child, &(dx, dy))| {
+                    let child_bounds = self
+                        .node_bounds(child, memo)
+                        .map(|(min_x, min_y, max_x, max_y)| (min_x + dx, min_y + dy, max_x + dx, max_y + dy));
+                    merge_bounds(bounds, child_bounds)
+                })
+            },
+        };
+        memo.insert(idx, bounds);
+        bounds
+    }
+
+    /// Rasterize the `width` x `height` window whose top left cell is at
+    /// `(x, y)` into a dense pattern.
+    pub fn rasterize(&self, x: i64, y: i64, width: u32, height: u32) -> RleData {
+        let mut data = RleData {
+            width,
+            height,
+            cells: vec![Cell::Dead; width as usize * height as usize],
+        };
+        let origin = -(1i64 << (self.level - 1));
+        self.paint(self.root, origin - x, origin - y, &mut data);
+        data
+    }
+
+    /// Paint a node whose top left corner is at `(x, y)` relative to the
+    /// window.
+    fn paint(&self, idx: u32, x: i64, y: i64, data: &mut RleData) {
+        if idx == 0 {
+            return;
+        }
+        let size = 1i64 << self.node_level(idx);
+        if x >= data.width as i64 || y >= data.height as i64 || x + size <= 0 || y + size <= 0 {
+            return;
+        }
+        match self.nodes[idx as usize] {
+            Node::Leaf(bits) => {
+                for bit in (0..64).filter(|bit| bits >> bit & 1 == 1) {
+                    let (cx, cy) = (x + bit % LEAF_SIZE, y + bit / LEAF_SIZE);
+                    if cx >= 0 && cy >= 0 && cx < data.width as i64 && cy < data.height as i64 {
+                        data.cells[(cy * data.width as i64 + cx) as usize] = Cell::Alive;
+                    }
+                }
+            },
+            Node::Branch { children, .. } => {
+                let half = size / 2;
+                self.paint(children[0], x, y, data);
+                self.paint(children[1], x + half, y, data);
+                self.paint(children[2], x, y + half, data);
+                self.paint(children[3], x + half, y + half, data);
+            },
+        }
+    }
+
+    /// Collect the alive cells into a sparse pattern covering their
+    /// bounding box.
+    pub fn to_pattern(&self) -> Pattern {
+        let mut coordinates = Vec::new();
+        self.collect(self.root, 0, 0, &mut coordinates);
+        Pattern::from_coordinates(&coordinates)
+    }
+
+    fn collect(&self, idx: u32, x: i64, y: i64, coordinates: &mut Vec<(i64, i64)>) {
+        if idx == 0 {
+            return;
+        }
+        match self.nodes[idx as usize] {
+            Node::Leaf(bits) => {
+                coordinates.extend(
+                    (0..64)
+                        .filter(|bit| bits >> bit & 1 == 1)
+                        .map(|bit| (x + bit % LEAF_SIZE, y + bit / LEAF_SIZE)),
+                );
+            },
+            Node::Branch { level, children } => {
+                let half = 1i64 << (level - 1);
+                self.collect(children[0], x, y, coordinates);
+                self.collect(children[1], x + half, y, coordinates);
+                self.collect(children[2], x, y + half, coordinates);
+                self.collect(children[3], x + half, y + half, coordinates);
+            },
+        }
+    }
+}
+
+fn merge_bounds(a: Option<(i64, i64, i64, i64)>, b: Option<(i64, i64, i64, i64)>) -> Option<(i64, i64, i64, i64)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Load a two-state macrocell file. Node lines are either 8x8 leaves written
+/// as rows of `.` and `*` ended by `$`, or `level nw ne sw se` where each
+/// child is the 1-based line number of an earlier node, or 0 if empty.
+pub fn load(text: &str) -> Result<Macrocell, String> {
+    let mut lines = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty());
+    match lines.next() {
+        Some(header) if header.starts_with(HEADER) => (),
+        _ => return Err(format!("macrocell input must start with `{}`", HEADER)),
+    }
+
+    let mut macrocell = Macrocell::empty();
+    // maps the node numbers of the file onto interned nodes
+    let mut file_nodes: Vec<u32> = vec![0];
+    let mut index = HashMap::new();
+
+    for line in lines {
+        if let Some(rule) = line.strip_prefix("#R") {
+            macrocell.rule = Some(String::from(rule.trim()));
+        } else if let Some(generation) = line.strip_prefix("#G") {
+            let generation = generation.trim();
+            macrocell.generation = generation
+                .parse()
+                .map_err(|_| format!("error parsing generation (`{}`)", generation))?;
+        } else if line.starts_with('#') {
+            continue;
+        } else if line.starts_with(|c: char| c.is_ascii_digit()) {
+            let numbers = line
+                .split_whitespace()
+                .map(|number| number.parse::<u32>().map_err(|_| format!("error parsing node (`{}`)", line)))
+                .collect::<Result<Vec<u32>, String>>()?;
+            if numbers.len() != 5 {
+                return Err(format!("expected `level nw ne sw se`, found `{}`", line));
+            }
+            let level = numbers[0];
+            if level <= LEAF_LEVEL {
+                return Err(format!("unsupported node level {} (only two-state macrocells are supported)", level));
+            }
+            if level > MAX_LEVEL {
+                return Err(format!("node level {} is too large", level));
+            }
+            let mut children = [0; 4];
+            for (child, &number) in children.iter_mut().zip(numbers[1..].iter()) {
+                *child = *file_nodes
+                    .get(number as usize)
+                    .ok_or_else(|| format!("node refers to unknown node {}", number))?;
+                if *child != 0 && macrocell.node_level(*child) != level - 1 {
+                    return Err(format!("node `{}` has a child of the wrong level", line));
+                }
+            }
+            file_nodes.push(macrocell.intern(Node::Branch { level, children }, &mut index));
+        } else {
+            let mut bits = 0u64;
+            let (mut row, mut col) = (0, 0);
+            for c in line.chars() {
+                match c {
+                    '.' => col += 1,
+                    '*' => {
+                        if row >= LEAF_SIZE || col >= LEAF_SIZE {
+                            return Err(format!("leaf `{}` is larger than 8x8", line));
+                        }
+                        bits |= 1 << (row * LEAF_SIZE + col);
+                        col += 1;
+                    },
+                    '$' => {
+                        row += 1;
+                        col = 0;
+                    },
+                    _ => return Err(format!("invalid macrocell character `{}`", c)),
+                }
+            }
+            file_nodes.push(macrocell.intern(Node::Leaf(bits), &mut index));
+        }
+    }
+
+    macrocell.root = *file_nodes.last().unwrap();
+    if macrocell.root != 0 {
+        macrocell.level = macrocell.node_level(macrocell.root);
+    }
+    Ok(macrocell)
+}
+
+/// Write a macrocell file. Nodes are written children first, so that every
+/// node only refers to lines above it.
+pub fn save(macrocell: &Macrocell) -> String {
+    let mut text = format!("{}\n", HEADER);
+    text.push_str(&format!("#R {}\n", macrocell.rule.as_deref().unwrap_or("B3/S23")));
+    if macrocell.generation != 0 {
+        text.push_str(&format!("#G {}\n", macrocell.generation));
+    }
+    let mut numbers = HashMap::new();
+    write_node(macrocell, macrocell.root, &mut numbers, &mut text);
+    text
+}
+
+fn write_node(macrocell: &Macrocell, idx: u32, numbers: &mut HashMap<u32, u32>, text: &mut String) -> u32 {
+    if idx == 0 {
+        return 0;
+    }
+    if let Some(&number) = numbers.get(&idx) {
+        return number;
+    }
+    match macrocell.nodes[idx as usize] {
+        Node::Leaf(bits) => {
+            let last_row = (0..LEAF_SIZE).rev().find(|row| bits >> (row * LEAF_SIZE) & 0xff != 0).unwrap_or(0);
+            for row in 0..=last_row {
+                let row_bits = bits >> (row * LEAF_SIZE) & 0xff;
+                for col in 0..(64 - row_bits.leading_zeros() as i64) {
+                    text.push(if row_bits >> col & 1 == 1 { '*' } else { '.' });
+                }
+                text.push('$');
+            }
+            text.push('\n');
+        },
+        Node::Branch { level, children } => {
+            let children = [
+                write_node(macrocell, children[0], numbers, text),
+                write_node(macrocell, children[1], numbers, text),
+                write_node(macrocell, children[2], numbers, text),
+                write_node(macrocell, children[3], numbers, text),
+            ];
+            text.push_str(&format!("{} {} {} {} {}\n", level, children[0], children[1], children[2], children[3]));
+        },
+    }
+    let number = numbers.len() as u32 + 1;
+    numbers.insert(idx, number);
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glider() -> Pattern {
+        Pattern::new(3, 3, vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)])
+    }
+
+    /// A macrocell with a single cell at each corner of a `2^level` square,
+    /// built by repeating the same node in the north west and south east.
+    fn diagonal(level: u32) -> String {
+        let mut text = String::from("[M2]\n#R B3/S23\n*$\n");
+        for (number, level) in (LEAF_LEVEL + 1..=level).enumerate() {
+            text.push_str(&format!("{} {} 0 0 {}\n", level, number + 1, number + 1));
+        }
+        text
+    }
+
+    #[test]
+    fn save_round_trips() {
+        let pattern = Pattern::new(40, 20, vec![(0, 0), (39, 0), (20, 10), (5, 19)]);
+        let mut macrocell = Macrocell::from_pattern(&pattern);
+        macrocell.generation = 12;
+        let loaded = load(&save(&macrocell)).unwrap();
+        assert_eq!(loaded.rule.as_deref(), Some("B3/S23"));
+        assert_eq!(loaded.generation, 12);
+        assert_eq!(loaded.level(), 6);
+        assert_eq!(loaded.to_pattern(), pattern);
+    }
+
+    #[test]
+    fn identical_nodes_are_shared() {
+        let macrocell = load(&diagonal(5)).unwrap();
+        // the empty node, one leaf and one branch per level
+        assert_eq!(macrocell.nodes.len(), 4);
+        assert_eq!(save(&macrocell), diagonal(5));
+    }
+
+    #[test]
+    fn rasterizes_a_window() {
+        // the root is 8x8 and centred on the origin
+        let macrocell = Macrocell::from_pattern(&glider());
+        let window = macrocell.rasterize(-4, -4, 3, 3);
+        assert_eq!(Pattern::from_rle_data(&window), glider());
+        // a window that only overlaps the bottom right corner
+        let window = macrocell.rasterize(-3, -3, 4, 4);
+        assert_eq!(Pattern::from_rle_data(&window), Pattern::new(4, 4, vec![(1, 0), (0, 1), (1, 1)]));
+    }
+
+    #[test]
+    fn population_and_bounding_box_of_a_deep_tree() {
+        let macrocell = load(&diagonal(40)).unwrap();
+        assert_eq!(macrocell.level(), 40);
+        assert_eq!(macrocell.population(), 1 << 37);
+        let origin = -(1i64 << 39);
+        let size = (1u64 << 40) - 7;
+        assert_eq!(macrocell.bounding_box(), Some((origin, origin, size, size)));
+        assert_eq!(load("[M2]\n").unwrap().bounding_box(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let errors = [
+            ("", "macrocell input must start with `[M2]`"),
+            ("x = 1, y = 1\no!", "macrocell input must start with `[M2]`"),
+            ("[M2]\n#G soon\n", "error parsing generation (`soon`)"),
+            ("[M2]\n*x$\n", "invalid macrocell character `x`"),
+            ("[M2]\n........*$\n", "leaf `........*$` is larger than 8x8"),
+            ("[M2]\n*$\n4 1 0 0\n", "expected `level nw ne sw se`, found `4 1 0 0`"),
+            ("[M2]\n*$\n4 1 0 0 a\n", "error parsing node (`4 1 0 0 a`)"),
+            ("[M2]\n*$\n4 2 0 0 0\n", "node refers to unknown node 2"),
+            ("[M2]\n*$\n5 1 0 0 0\n", "node `5 1 0 0 0` has a child of the wrong level"),
+            ("[M2]\n3 0 0 0 0\n", "unsupported node level 3 (only two-state macrocells are supported)"),
+            ("[M2]\n63 0 0 0 0\n", "node level 63 is too large"),
+        ];
+        for &(text, error) in errors.iter() {
+            assert_eq!(load(text).err(), Some(String::from(error)), "{:?}", text);
+        }
+    }
+}
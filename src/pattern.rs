@@ -0,0 +1,214 @@
+use crate::rle_loader::RleData;
+use crate::universe::Cell;
+
+/// A pattern stored as the coordinates of its alive cells, so its size
+/// depends on the population rather than on the area of its bounding box.
+///
+/// Coordinates are `(x, y)` from the top left corner of the pattern's
+/// `width` x `height` box, sorted by row and then by column.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pattern {
+    width: u32,
+    height: u32,
+    cells: Vec<(u32, u32)>,
+}
+
+impl Pattern {
+    /// Create a pattern from the coordinates of its alive cells, which may
+    /// be in any order and contain duplicates.
+    pub fn new(width: u32, height: u32, mut cells: Vec<(u32, u32)>) -> Self {
+        if let Some(&(x, y)) = cells.iter().find(|&&(x, y)| x >= width || y >= height) {
+            panic!("cell ({}, {}) lies outside the {}x{} pattern", x, y, width, height);
+        }
+        cells.sort_unstable_by_key(|&(x, y)| (y, x));
+        cells.dedup();
+        Pattern { width, height, cells }
+    }
+
+    /// Create a pattern from the coordinates of its alive cells. The pattern
+    /// covers the bounding box of the coordinates.
+    pub fn from_coordinates(coordinates: &[(i64, i64)]) -> Self {
+        if coordinates.is_empty() {
+            return Pattern::default();
+        }
+        let min_x = coordinates.iter().map(|&(x, _)| x).min().unwrap();
+        let max_x = coordinates.iter().map(|&(x, _)| x).max().unwrap();
+        let min_y = coordinates.iter().map(|&(_, y)| y).min().unwrap();
+        let max_y = coordinates.iter().map(|&(_, y)| y).max().unwrap();
+        let cells = coordinates
+            .iter()
+            .map(|&(x, y)| ((x - min_x) as u32, (y - min_y) as u32))
+            .collect();
+        Pattern::new((max_x - min_x + 1) as u32, (max_y - min_y + 1) as u32, cells)
+    }
+
+    /// Create a pattern from a row-major grid of cells.
+    pub fn from_cells(width: u32, height: u32, cells: &[Cell]) -> Self {
+        let alive = cells
+            .iter()
+            .enumerate()
+            .filter(|&(_, &cell)| cell == Cell::Alive)
+            .map(|(idx, _)| (idx as u32 % width, idx as u32 / width))
+            .collect();
+        Pattern { width, height, cells: alive }
+    }
+
+    pub fn from_rle_data(data: &RleData) -> Self {
+        Pattern::from_cells(data.width, data.height, &data.cells)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The alive cells as `(x, y)`, sorted by row and then by column.
+    pub fn cells(&self) -> &[(u32, u32)] {
+        &self.cells
+    }
+
+    pub fn population(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_alive(&self, x: u32, y: u32) -> bool {
+        self.cells.binary_search_by_key(&(y, x), |&(x, y)| (y, x)).is_ok()
+    }
+
+    /// The alive cells of row `y`, as a sorted slice of `(x, y)`.
+    pub fn row(&self, y: u32) -> &[(u32, u32)] {
+        let start = self.cells.partition_point(|&(_, row)| row < y);
+        let end = self.cells.partition_point(|&(_, row)| row <= y);
+        &self.cells[start..end]
+    }
+
+    /// The smallest pattern containing all alive cells, or an empty pattern
+    /// if there are none.
+    pub fn trimmed(&self) -> Pattern {
+        let coordinates = self.cells.iter().map(|&(x, y)| (x as i64, y as i64)).collect::<Vec<(i64, i64)>>();
+        Pattern::from_coordinates(&coordinates)
+    }
+
+    /// Run the pattern forward `generations` generations on an unbounded
+    /// plane. The box grows by `generations` cells on every side, the
+    /// furthest any cell can spread, so the cell at `(x, y)` in this pattern
+    /// is at `(x + generations, y + generations)` in the result.
+    pub fn advanced(&self, generations: u32) -> Pattern {
+        let width = self.width + 2 * generations;
+        let height = self.height + 2 * generations;
+        let mut grid = vec![Cell::Dead; (width * height) as usize];
+        for &(x, y) in &self.cells {
+            grid[((y + generations) * width + x + generations) as usize] = Cell::Alive;
+        }
+
+        for _ in 0..generations {
+            let mut next = grid.clone();
+            for row in 0..height {
+                for col in 0..width {
+                    // cells beyond the box are dead
+                    let mut live_neighbors = 0;
+                    for neighbor_row in row.saturating_sub(1)..(row + 2).min(height) {
+                        for neighbor_col in col.saturating_sub(1)..(col + 2).min(width) {
+                            if (neighbor_row, neighbor_col) != (row, col) {
+                                live_neighbors += grid[(neighbor_row * width + neighbor_col) as usize] as u8;
+                            }
+                        }
+                    }
+                    let idx = (row * width + col) as usize;
+                    next[idx] = grid[idx].next_state(live_neighbors);
+                }
+            }
+            grid = next;
+        }
+
+        Pattern::from_cells(width, height, &grid)
+    }
+
+    /// Expand into a dense grid.
+    pub fn to_rle_data(&self) -> RleData {
+        let mut cells = vec![Cell::Dead; (self.width * self.height) as usize];
+        for &(x, y) in &self.cells {
+            cells[(y * self.width + x) as usize] = Cell::Alive;
+        }
+        RleData {
+            width: self.width,
+            height: self.height,
+            cells,
+        }
+    }
+
+    /// Copy the pattern into the centre of a `width` x `height` grid. Any part
+    /// of the pattern that does not fit is clipped.
+    pub fn embed(&self, width: u32, height: u32) -> Vec<Cell> {
+        let mut grid = vec![Cell::Dead; (width * height) as usize];
+        let row_offset = height as i64 / 2 - self.height as i64 / 2;
+        let col_offset = width as i64 / 2 - self.width as i64 / 2;
+        for &(x, y) in &self.cells {
+            let target_row = y as i64 + row_offset;
+            let target_col = x as i64 + col_offset;
+            if target_row < 0 || target_col < 0 || target_row >= height as i64 || target_col >= width as i64 {
+                continue;
+            }
+            grid[(target_row * width as i64 + target_col) as usize] = Cell::Alive;
+        }
+        grid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_sorts_and_dedups_cells() {
+        let pattern = Pattern::new(3, 2, vec![(2, 1), (0, 1), (1, 0), (2, 1), (1, 0)]);
+        assert_eq!(pattern.cells(), &[(1, 0), (0, 1), (2, 1)]);
+        assert_eq!(pattern.population(), 3);
+        assert!(pattern.is_alive(0, 1));
+        assert!(!pattern.is_alive(0, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "cell (3, 0) lies outside the 3x2 pattern")]
+    fn new_rejects_cells_outside_the_box() {
+        Pattern::new(3, 2, vec![(0, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn from_coordinates_covers_their_bounding_box() {
+        let pattern = Pattern::from_coordinates(&[(-2, 5), (1, -1), (-2, -1)]);
+        assert_eq!((pattern.width(), pattern.height()), (4, 7));
+        assert_eq!(pattern.cells(), &[(0, 0), (3, 0), (0, 6)]);
+        assert_eq!(Pattern::from_coordinates(&[]), Pattern::default());
+    }
+
+    #[test]
+    fn row_returns_the_cells_of_one_row() {
+        let pattern = Pattern::new(4, 4, vec![(0, 0), (3, 1), (1, 1), (2, 3)]);
+        assert_eq!(pattern.row(1), &[(1, 1), (3, 1)]);
+        assert_eq!(pattern.row(2), &[]);
+        assert_eq!(pattern.row(3), &[(2, 3)]);
+        assert_eq!(pattern.row(9), &[]);
+    }
+
+    #[test]
+    fn trimmed_removes_empty_margins() {
+        let pattern = Pattern::new(6, 5, vec![(2, 1), (4, 3)]);
+        assert_eq!(pattern.trimmed(), Pattern::new(3, 3, vec![(0, 0), (2, 2)]));
+        assert_eq!(Pattern::new(6, 5, vec![]).trimmed(), Pattern::default());
+    }
+
+    #[test]
+    fn embed_centres_and_clips() {
+        let blinker = Pattern::new(3, 1, vec![(0, 0), (1, 0), (2, 0)]);
+        let grid = blinker.embed(5, 3);
+        let alive = (0..15).filter(|&idx| grid[idx] == Cell::Alive).collect::<Vec<usize>>();
+        assert_eq!(alive, vec![6, 7, 8]);
+        // only the top middle cell of a glider fits in a 2x2 grid
+        let glider = Pattern::new(3, 3, vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(glider.embed(2, 2), vec![Cell::Dead, Cell::Alive, Cell::Dead, Cell::Dead]);
+    }
+}
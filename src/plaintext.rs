@@ -0,0 +1,106 @@
+use crate::pattern::Pattern;
+
+/// Check whether a pattern string is in the plaintext (`.cells`) format
+/// rather than RLE, by looking at its first non-empty line.
+pub fn is_plaintext(text: &str) -> bool {
+    match text.lines().map(|line| line.trim()).find(|line| !line.is_empty()) {
+        Some(line) => line.starts_with('!') || line.chars().all(|c| c == '.' || c == 'O' || c == '*'),
+        None => false,
+    }
+}
+
+/// Load a pattern in the plaintext format. Lines starting with `!` are
+/// comments (including the `!Name:` header), `.` is a dead cell and `O` (or
+/// `*`) an alive cell. The width is that of the widest row.
+pub fn load(text: &str) -> Result<Pattern, String> {
+    let mut cells = Vec::new();
+    let mut width = 0;
+    let mut height = 0;
+
+    let rows = text
+        .lines()
+        .map(|line| line.trim_end())
+        // remove comment lines
+        .filter(|line| !line.starts_with('!'));
+    for (y, row) in rows.enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            match c {
+                '.' => (),
+                'O' | '*' => cells.push((x as u32, y as u32)),
+                _ => return Err(format!("invalid plaintext character `{}` on row {}", c, y + 1)),
+            }
+        }
+        // trailing empty lines are not part of the pattern
+        if !row.is_empty() {
+            width = width.max(row.chars().count() as u32);
+            height = y as u32 + 1;
+        }
+    }
+
+    Ok(Pattern::new(width, height, cells))
+}
+
+/// Write a pattern in the plaintext format, with an optional `!Name:` header.
+/// Trailing dead cells are left off each row, but an empty row keeps one `.`
+/// so it is not read back as the end of the pattern.
+pub fn save(pattern: &Pattern, name: Option<&str>) -> String {
+    let mut text = String::new();
+    if let Some(name) = name {
+        text.push_str(&format!("!Name: {}\n", name));
+    }
+    for y in 0..pattern.height() {
+        let mut col = 0;
+        for &(x, _) in pattern.row(y) {
+            text.extend((col..x).map(|_| '.'));
+            text.push('O');
+            col = x + 1;
+        }
+        if col == 0 {
+            text.push('.');
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_plaintext() {
+        assert!(is_plaintext("!Name: Blinker\nOOO\n"));
+        assert!(is_plaintext("\n.O\n"));
+        assert!(!is_plaintext("x = 3, y = 1\n3o!"));
+        assert!(!is_plaintext(""));
+    }
+
+    #[test]
+    fn loads_rows_and_skips_comments() {
+        let pattern = load("!Name: Glider\n!\n.O\n..O\nOOO\n\n").unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (3, 3));
+        assert_eq!(pattern.cells(), &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn save_round_trips_empty_rows() {
+        let pattern = Pattern::new(3, 4, vec![(0, 0), (2, 0), (1, 3)]);
+        let text = save(&pattern, Some("Gap"));
+        assert_eq!(text, "!Name: Gap\nO.O\n.\n.\n.O\n");
+        assert_eq!(load(&text), Ok(pattern));
+    }
+
+    #[test]
+    fn save_keeps_trailing_empty_rows() {
+        let pattern = Pattern::new(1, 3, vec![(0, 0)]);
+        assert_eq!(load(&save(&pattern, None)), Ok(pattern));
+        let empty = Pattern::new(1, 2, vec![]);
+        assert_eq!(load(&save(&empty, None)), Ok(empty));
+    }
+
+    #[test]
+    fn rejects_unexpected_characters() {
+        assert_eq!(load(".O\nOxO\n"), Err(String::from("invalid plaintext character `x` on row 2")));
+        assert!(load("!Name: Bad\nO O\n").is_err());
+    }
+}
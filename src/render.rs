@@ -0,0 +1,1377 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::UniverseConfig;
+use crate::selection::Selection;
+use crate::universe::Cell;
+
+#[cfg(feature = "web")]
+use wasm_bindgen::prelude::*;
+
+/// How a universe is drawn to its canvas.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderMode {
+    /// Draw the lines and every cell with canvas path calls.
+    Canvas,
+    /// Rasterize the visible region into a pixel buffer in Rust and copy it
+    /// to the canvas in a single call, which is much faster for large grids.
+    PixelBuffer,
+}
+
+/// How cells are shaded when they are smaller than a pixel.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubCellShading {
+    /// A pixel is alive if any of its cells is alive.
+    Any,
+    /// A pixel is shaded between the dead and alive colours by the fraction
+    /// of its cells that are alive.
+    Density,
+}
+
+/// The shape of alive cells.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CellShape {
+    Square,
+    Circle,
+    /// A square with corners rounded by the configured corner radius.
+    Rounded,
+    Diamond,
+}
+
+/// Cells smaller than this, in pixels, are drawn without grid lines.
+pub const GRID_LINE_MIN_CELL_SIZE: f64 = 4.0;
+
+/// A cell of a universe, by row and column.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellPosition {
+    pub row: u32,
+    pub col: u32,
+}
+
+/// A rectangle on the canvas, in pixels.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// What a renderer draws: the visible region of a universe and its styling.
+#[derive(Clone, Copy)]
+pub struct View<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub cells: &'a [Cell],
+    pub visible_rows: u32,
+    pub visible_columns: u32,
+    pub visible_row_start_position: u32,
+    pub visible_column_start_position: u32,
+    /// The zoom level, in pixels per cell. Below 1, several cells share a
+    /// pixel.
+    pub cell_size: f64,
+    /// The selected cells, outlined over the grid.
+    pub selection: Option<Selection>,
+    pub config: &'a UniverseConfig,
+}
+
+impl View<'_> {
+    /// The cell at `(row, col)` counted from the top left of the visible
+    /// region.
+    pub fn visible_cell(&self, row: u32, col: u32) -> Cell {
+        let row = self.visible_row_start_position + row;
+        let col = self.visible_column_start_position + col;
+        self.cells[(row * self.width + col) as usize]
+    }
+}
+
+/// The position of the visible grid on a surface, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub x_offset: f64,
+    pub y_offset: f64,
+    pub cell_size: f64,
+    pub line_width: f64,
+    pub border_width: f64,
+    /// Width of the visible cells and the lines between them, without the
+    /// border.
+    pub grid_width: f64,
+    pub grid_height: f64,
+}
+
+impl Layout {
+    /// Centre the visible grid on a `width` x `height` surface, in device
+    /// pixels.
+    pub fn new(view: &View, width: u32, height: u32) -> Self {
+        let (cell_size, line_width) = Layout::device_metrics(view.config, view.cell_size);
+        let grid_width = view.visible_columns as f64 * (cell_size + line_width) - line_width;
+        let grid_height = view.visible_rows as f64 * (cell_size + line_width) - line_width;
+        Layout {
+            x_offset: ((width as f64 - grid_width) / 2.0).floor(),
+            y_offset: ((height as f64 - grid_height) / 2.0).floor(),
+            cell_size,
+            line_width,
+            border_width: (view.config.border_width as f64 * view.config.device_pixel_ratio).round(),
+            grid_width,
+            grid_height,
+        }
+    }
+
+    /// The size of a cell and the width of the lines between cells in device
+    /// pixels, at a zoom level in CSS pixels per cell. Both are snapped to
+    /// whole device pixels to keep the grid crisp, unless several cells share
+    /// a pixel. Lines are dropped when cells are too small for them, or when
+    /// neither the lines nor the major lines are enabled.
+    pub fn device_metrics(config: &UniverseConfig, cell_size: f64) -> (f64, f64) {
+        let ratio = config.device_pixel_ratio;
+        let any_lines = config.lines_enabled || config.get_major_line_interval() > 0;
+        let line_width = if any_lines && cell_size >= GRID_LINE_MIN_CELL_SIZE && config.line_width > 0 {
+            (config.line_width as f64 * ratio).round().max(1.0)
+        } else {
+            0.0
+        };
+        let cell_size = cell_size * ratio;
+        let cell_size = if cell_size >= 1.0 { cell_size.round() } else { cell_size };
+        (cell_size, line_width)
+    }
+
+    /// Whether several cells share a pixel.
+    pub fn is_sub_cell(&self) -> bool {
+        self.cell_size < 1.0
+    }
+
+    /// The visible cells, as `(row, col)` ranges, covered by the pixel at
+    /// `(x, y)` when cells are smaller than a pixel.
+    fn pixel_cells(&self, view: &View, x: u32, y: u32) -> (std::ops::Range<u32>, std::ops::Range<u32>) {
+        let span = |pixel: u32, offset: f64, count: u32| {
+            let start = ((pixel as f64 - offset) / self.cell_size).floor().max(0.0);
+            let end = ((pixel as f64 + 1.0 - offset) / self.cell_size).ceil().max(0.0);
+            (start as u32).min(count)..(end as u32).min(count)
+        };
+        (span(y, self.y_offset, view.visible_rows), span(x, self.x_offset, view.visible_columns))
+    }
+
+    /// The top left corner of the visible cell at `(row, col)`.
+    pub fn cell_origin(&self, row: u32, col: u32) -> (f64, f64) {
+        (
+            self.x_offset + col as f64 * (self.cell_size + self.line_width),
+            self.y_offset + row as f64 * (self.cell_size + self.line_width),
+        )
+    }
+
+    /// The major grid lines crossing the visible grid, as `(x, y, width,
+    /// height)` rectangles.
+    pub fn major_lines(&self, view: &View) -> Vec<(f64, f64, f64, f64)> {
+        let interval = view.config.get_major_line_interval();
+        if interval == 0 || self.line_width == 0.0 {
+            return Vec::new();
+        }
+        // a line lies before each cell whose column or row is a multiple of
+        // the interval
+        let columns = (1..view.visible_columns)
+            .filter(|&col| (view.visible_column_start_position + col) % interval == 0)
+            .map(|col| (self.cell_origin(0, col).0 - self.line_width, self.y_offset, self.line_width, self.grid_height));
+        let rows = (1..view.visible_rows)
+            .filter(|&row| (view.visible_row_start_position + row) % interval == 0)
+            .map(|row| (self.x_offset, self.cell_origin(row, 0).1 - self.line_width, self.grid_width, self.line_width));
+        columns.chain(rows).collect()
+    }
+
+    /// The outline of the visible part of the selection, as four
+    /// `(x, y, width, height)` rectangles lying on the grid lines around it.
+    /// The outline is at least a pixel wide, so without grid lines it covers
+    /// the outermost selected cells.
+    pub fn selection_outline(&self, view: &View) -> Option<[(f64, f64, f64, f64); 4]> {
+        let selection = view.selection?;
+        let first_row = selection.row.max(view.visible_row_start_position);
+        let first_col = selection.col.max(view.visible_column_start_position);
+        let last_row = (selection.row + selection.height).min(view.visible_row_start_position + view.visible_rows);
+        let last_col = (selection.col + selection.width).min(view.visible_column_start_position + view.visible_columns);
+        if first_row >= last_row || first_col >= last_col {
+            return None;
+        }
+        let (left, top) = self.cell_origin(first_row - view.visible_row_start_position, first_col - view.visible_column_start_position);
+        let (right, bottom) = self.cell_origin(last_row - 1 - view.visible_row_start_position, last_col - 1 - view.visible_column_start_position);
+        let (left, top) = (left - self.line_width, top - self.line_width);
+        let (right, bottom) = (right + self.cell_size + self.line_width, bottom + self.cell_size + self.line_width);
+        let thickness = self.line_width.max(1.0);
+        let (width, height) = (right - left, bottom - top);
+        Some([
+            (left, top, width, thickness),
+            (left, bottom - thickness, width, thickness),
+            (left, top, thickness, height),
+            (right - thickness, top, thickness, height),
+        ])
+    }
+
+    /// The visible cell, as `(row, col)`, under the point `(x, y)`. Points on
+    /// the grid lines, the border or outside the grid have no cell.
+    pub fn visible_cell_at(&self, x: f64, y: f64) -> Option<(u32, u32)> {
+        let pitch = self.cell_size + self.line_width;
+        let locate = |offset: f64, extent: f64| {
+            if offset < 0.0 || offset >= extent {
+                return None;
+            }
+            let index = (offset / pitch).floor();
+            if offset - index * pitch >= self.cell_size {
+                return None;
+            }
+            Some(index as u32)
+        };
+        let col = locate(x - self.x_offset, self.grid_width)?;
+        let row = locate(y - self.y_offset, self.grid_height)?;
+        Some((row, col))
+    }
+}
+
+/// A drawing backend for universes.
+pub trait Renderer {
+    fn render(&mut self, view: &View);
+
+    /// Repaint only the given cells, as `(row, col)` within the visible
+    /// region, over an earlier `render` of the same layout. Backends that
+    /// cannot repaint in place render everything.
+    fn render_cells(&mut self, view: &View, cells: &[(u32, u32)]) {
+        let _ = cells;
+        self.render(view);
+    }
+}
+
+/// A colour with 8 bit channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rgba(pub u8, pub u8, pub u8, pub u8);
+
+impl Rgba {
+    pub const TRANSPARENT: Rgba = Rgba(0, 0, 0, 0);
+    pub const BLACK: Rgba = Rgba(0, 0, 0, 255);
+
+    /// Blend towards `other` by `t`, from 0 to 1.
+    pub fn mix(self, other: Rgba, t: f64) -> Rgba {
+        let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+        Rgba(
+            channel(self.0, other.0),
+            channel(self.1, other.1),
+            channel(self.2, other.2),
+            channel(self.3, other.3),
+        )
+    }
+
+    /// Parse a CSS colour. The supported forms are
+    ///
+    /// * hex: `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`
+    /// * `rgb()` and `rgba()` with numbers from 0 to 255 or percentages
+    /// * `hsl()` and `hsla()` with the hue in degrees
+    /// * the CSS colour keywords and `transparent`
+    ///
+    /// Functions take comma or space separated arguments, with an optional
+    /// alpha as a number from 0 to 1 or a percentage.
+    ///   e.g. "#0f08", "rgb(255 0 0 / 50%)", "hsl(120, 100%, 25%)" or "Teal"
+    pub fn parse(color: &str) -> Option<Rgba> {
+        let color = color.trim().to_ascii_lowercase();
+        if let Some(hex) = color.strip_prefix('#') {
+            let digits = hex.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect::<Option<Vec<u8>>>()?;
+            return match *digits.as_slice() {
+                [r, g, b] => Some(Rgba(r * 17, g * 17, b * 17, 255)),
+                [r, g, b, a] => Some(Rgba(r * 17, g * 17, b * 17, a * 17)),
+                [r1, r2, g1, g2, b1, b2] => Some(Rgba(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2, 255)),
+                [r1, r2, g1, g2, b1, b2, a1, a2] => Some(Rgba(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2, a1 * 16 + a2)),
+                _ => None,
+            };
+        }
+        if let Some((function, arguments)) = color.strip_suffix(')').and_then(|color| color.split_once('(')) {
+            return Rgba::parse_function(function.trim_end(), arguments);
+        }
+        if color == "transparent" {
+            return Some(Rgba::TRANSPARENT);
+        }
+        let idx = NAMED_COLORS.binary_search_by_key(&color.as_str(), |&(name, _)| name).ok()?;
+        let rgb = NAMED_COLORS[idx].1;
+        Some(Rgba((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255))
+    }
+
+    /// Parse the arguments of `rgb()`, `rgba()`, `hsl()` or `hsla()`.
+    fn parse_function(function: &str, arguments: &str) -> Option<Rgba> {
+        let arguments = arguments.replace([',', '/'], " ");
+        let arguments = arguments.split_whitespace().collect::<Vec<&str>>();
+        let (channels, alpha) = match *arguments.as_slice() {
+            [a, b, c] => ([a, b, c], 1.0),
+            [a, b, c, alpha] => ([a, b, c], percentage_or(alpha, 1.0)?),
+            _ => return None,
+        };
+        let (r, g, b) = match function {
+            "rgb" | "rgba" => {
+                let channel = |value: &str| percentage_or(value, 255.0);
+                (channel(channels[0])?, channel(channels[1])?, channel(channels[2])?)
+            },
+            "hsl" | "hsla" => {
+                let hue = channels[0].strip_suffix("deg").unwrap_or(channels[0]).parse::<f64>().ok()?;
+                let saturation = channels[1].strip_suffix('%')?.parse::<f64>().ok()? / 100.0;
+                let lightness = channels[2].strip_suffix('%')?.parse::<f64>().ok()? / 100.0;
+                hsl_to_rgb(hue, saturation.clamp(0.0, 1.0), lightness.clamp(0.0, 1.0))
+            },
+            _ => return None,
+        };
+        let byte = |value: f64, max: f64| (value.clamp(0.0, max) / max * 255.0).round() as u8;
+        Some(Rgba(byte(r, 255.0), byte(g, 255.0), byte(b, 255.0), byte(alpha, 1.0)))
+    }
+}
+
+/// A number, or a percentage of `max`.
+fn percentage_or(value: &str, max: f64) -> Option<f64> {
+    match value.strip_suffix('%') {
+        Some(percentage) => Some(percentage.parse::<f64>().ok()? / 100.0 * max),
+        None => value.parse::<f64>().ok(),
+    }
+}
+
+/// Convert a hue in degrees and a saturation and lightness from 0 to 1 to
+/// red, green and blue from 0 to 255.
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (f64, f64, f64) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let channel = |n: f64| {
+        let k = (n + hue / 30.0).rem_euclid(12.0);
+        (lightness - chroma / 2.0 * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)) * 255.0
+    };
+    (channel(0.0), channel(8.0), channel(4.0))
+}
+
+/// The CSS colour keywords as `0xrrggbb`, sorted by name.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+/// The colours of a view, parsed once per frame. Colours that `Rgba::parse`
+/// does not support are black.
+struct Palette {
+    line: Rgba,
+    alive: Rgba,
+    dead: Rgba,
+    selection: Rgba,
+    major: Rgba,
+    glow: Rgba,
+}
+
+impl Palette {
+    fn new(config: &UniverseConfig) -> Self {
+        let color = |color: String| Rgba::parse(&color).unwrap_or(Rgba::BLACK);
+        Palette {
+            line: color(config.get_line_color()),
+            alive: color(config.get_cell_alive_color()),
+            dead: color(config.get_cell_dead_color()),
+            selection: color(config.get_selection_color()),
+            major: color(config.get_major_line_color()),
+            glow: color(config.get_glow_color()),
+        }
+    }
+}
+
+/// The shape of alive cells, in device pixels.
+struct CellStyle {
+    shape: CellShape,
+    /// Half the width of the shape, which is centred in the square of its
+    /// cell.
+    half: f64,
+    corner_radius: f64,
+    glow: f64,
+}
+
+impl CellStyle {
+    fn new(config: &UniverseConfig, layout: &Layout) -> Self {
+        let ratio = config.device_pixel_ratio;
+        let gap = (config.get_cell_gap() as f64 * ratio).round();
+        let half = (layout.cell_size / 2.0 - gap).max(0.5);
+        CellStyle {
+            shape: config.get_cell_shape(),
+            half,
+            corner_radius: (config.get_corner_radius() as f64 * ratio).min(half),
+            glow: config.get_glow_radius() as f64 * ratio,
+        }
+    }
+
+    /// Whether alive cells fill their whole square, so they can be drawn as
+    /// plain rectangles.
+    fn is_plain(&self, layout: &Layout) -> bool {
+        self.shape == CellShape::Square && 2.0 * self.half >= layout.cell_size && self.glow == 0.0
+    }
+
+    /// The signed distance from the point `(x, y)`, relative to the centre of
+    /// a cell, to the edge of the shape. It is negative inside the shape.
+    fn distance(&self, x: f64, y: f64) -> f64 {
+        let rounded_square = |radius: f64| {
+            let (dx, dy) = (x.abs() - self.half + radius, y.abs() - self.half + radius);
+            let outside = (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt();
+            outside + dx.max(dy).min(0.0) - radius
+        };
+        match self.shape {
+            CellShape::Square => rounded_square(0.0),
+            CellShape::Rounded => rounded_square(self.corner_radius),
+            CellShape::Circle => (x * x + y * y).sqrt() - self.half,
+            CellShape::Diamond => (x.abs() + y.abs() - self.half) / std::f64::consts::SQRT_2,
+        }
+    }
+}
+
+/// Renders into a buffer of RGBA pixels, row by row, in the layout of
+/// `ImageData`.
+pub struct FramebufferRenderer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl FramebufferRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        FramebufferRenderer {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Rgba {
+        let idx = ((y * self.width + x) * 4) as usize;
+        Rgba(self.pixels[idx], self.pixels[idx + 1], self.pixels[idx + 2], self.pixels[idx + 3])
+    }
+
+    /// Copy the pixels of the `width` x `height` rectangle whose top left
+    /// pixel is `(x, y)`, row by row. The rectangle must lie in the buffer.
+    pub fn region(&self, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for row in y..y + height {
+            let start = ((row * self.width + x) * 4) as usize;
+            pixels.extend_from_slice(&self.pixels[start..start + (width * 4) as usize]);
+        }
+        pixels
+    }
+
+    /// Fill a rectangle, clipped to the buffer. Edges are rounded to the
+    /// nearest pixel.
+    pub fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64, color: Rgba) {
+        let clip = |value: f64, max: u32| value.round().max(0.0).min(max as f64) as u32;
+        let (left, right) = (clip(x, self.width), clip(x + width, self.width));
+        let (top, bottom) = (clip(y, self.height), clip(y + height, self.height));
+        for row in top..bottom {
+            let start = ((row * self.width + left) * 4) as usize;
+            let end = ((row * self.width + right) * 4) as usize;
+            for pixel in self.pixels[start..end].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[color.0, color.1, color.2, color.3]);
+            }
+        }
+    }
+
+    /// Shade the pixel at `(x, y)` from the cells it covers, when cells are
+    /// smaller than a pixel.
+    fn shade_pixel(&mut self, view: &View, layout: &Layout, palette: &Palette, x: u32, y: u32) {
+        let (rows, cols) = layout.pixel_cells(view, x, y);
+        let total = rows.len() * cols.len();
+        if total == 0 {
+            return;
+        }
+        let alive = rows
+            .flat_map(|row| cols.clone().map(move |col| (row, col)))
+            .filter(|&(row, col)| view.visible_cell(row, col) == Cell::Alive)
+            .count();
+        let color = match view.config.get_sub_cell_shading() {
+            SubCellShading::Any if alive > 0 => palette.alive,
+            SubCellShading::Any => palette.dead,
+            SubCellShading::Density => palette.dead.mix(palette.alive, alive as f64 / total as f64),
+        };
+        self.set_pixel(x, y, color);
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, color: Rgba) {
+        let idx = ((y * self.width + x) * 4) as usize;
+        self.pixels[idx..idx + 4].copy_from_slice(&[color.0, color.1, color.2, color.3]);
+    }
+
+    /// The pixels of the buffer covered by the visible grid.
+    fn grid_pixels(&self, layout: &Layout) -> (std::ops::Range<u32>, std::ops::Range<u32>) {
+        let clip = |value: f64, max: u32| value.max(0.0).min(max as f64) as u32;
+        (
+            clip(layout.y_offset.floor(), self.height)..clip((layout.y_offset + layout.grid_height).ceil(), self.height),
+            clip(layout.x_offset.floor(), self.width)..clip((layout.x_offset + layout.grid_width).ceil(), self.width),
+        )
+    }
+
+    /// Fill the visible cell at `(row, col)` with the colour of its state.
+    fn fill_cell(&mut self, view: &View, layout: &Layout, palette: &Palette, style: &CellStyle, row: u32, col: u32) {
+        let alive = view.visible_cell(row, col) == Cell::Alive;
+        let (x, y) = layout.cell_origin(row, col);
+        if !alive || style.is_plain(layout) {
+            let color = if alive { palette.alive } else { palette.dead };
+            self.fill_rect(x, y, layout.cell_size, layout.cell_size, color);
+            return;
+        }
+        // shade each pixel of the square by its distance to the edge of the
+        // shape, which also antialiases the edge
+        let clip = |value: f64, max: u32| value.round().max(0.0).min(max as f64) as u32;
+        let (center_x, center_y) = (x + layout.cell_size / 2.0, y + layout.cell_size / 2.0);
+        for pixel_y in clip(y, self.height)..clip(y + layout.cell_size, self.height) {
+            for pixel_x in clip(x, self.width)..clip(x + layout.cell_size, self.width) {
+                let distance = style.distance(pixel_x as f64 + 0.5 - center_x, pixel_y as f64 + 0.5 - center_y);
+                let mut color = palette.dead;
+                if distance > 0.0 && distance < style.glow {
+                    color = color.mix(palette.glow, 1.0 - distance / style.glow);
+                }
+                let color = color.mix(palette.alive, (0.5 - distance).clamp(0.0, 1.0));
+                self.set_pixel(pixel_x, pixel_y, color);
+            }
+        }
+    }
+
+    fn outline_selection(&mut self, view: &View, layout: &Layout, palette: &Palette) {
+        for &(x, y, width, height) in layout.selection_outline(view).iter().flatten() {
+            self.fill_rect(x, y, width, height, palette.selection);
+        }
+    }
+}
+
+impl Renderer for FramebufferRenderer {
+    fn render(&mut self, view: &View) {
+        let layout = Layout::new(view, self.width, self.height);
+        let palette = Palette::new(view.config);
+
+        self.pixels.iter_mut().for_each(|byte| *byte = 0);
+
+        // the border and the lines between cells, which the cells are then
+        // drawn over
+        self.fill_rect(
+            layout.x_offset - layout.border_width,
+            layout.y_offset - layout.border_width,
+            layout.grid_width + 2.0 * layout.border_width,
+            layout.grid_height + 2.0 * layout.border_width,
+            palette.line,
+        );
+        if !view.config.lines_enabled {
+            // only the major lines are drawn
+            self.fill_rect(layout.x_offset, layout.y_offset, layout.grid_width, layout.grid_height, palette.dead);
+        }
+        for (x, y, width, height) in layout.major_lines(view) {
+            self.fill_rect(x, y, width, height, palette.major);
+        }
+
+        if layout.is_sub_cell() {
+            let (rows, cols) = self.grid_pixels(&layout);
+            for y in rows {
+                for x in cols.clone() {
+                    self.shade_pixel(view, &layout, &palette, x, y);
+                }
+            }
+        } else {
+            let style = CellStyle::new(view.config, &layout);
+            for row in 0..view.visible_rows {
+                for col in 0..view.visible_columns {
+                    self.fill_cell(view, &layout, &palette, &style, row, col);
+                }
+            }
+        }
+        self.outline_selection(view, &layout, &palette);
+    }
+
+    fn render_cells(&mut self, view: &View, cells: &[(u32, u32)]) {
+        let layout = Layout::new(view, self.width, self.height);
+        let palette = Palette::new(view.config);
+        if layout.is_sub_cell() {
+            // reshade each pixel a changed cell overlaps, which is more than
+            // one when the cell straddles a pixel edge
+            let clip = |value: f64, max: u32| value.max(0.0).min(max as f64) as u32;
+            let mut pixels = Vec::new();
+            for &(row, col) in cells {
+                let (x, y) = layout.cell_origin(row, col);
+                for pixel_y in clip(y.floor(), self.height)..clip((y + layout.cell_size).ceil(), self.height) {
+                    for pixel_x in clip(x.floor(), self.width)..clip((x + layout.cell_size).ceil(), self.width) {
+                        pixels.push((pixel_x, pixel_y));
+                    }
+                }
+            }
+            pixels.sort_unstable();
+            pixels.dedup();
+            for (x, y) in pixels {
+                self.shade_pixel(view, &layout, &palette, x, y);
+            }
+        } else {
+            let style = CellStyle::new(view.config, &layout);
+            for &(row, col) in cells {
+                self.fill_cell(view, &layout, &palette, &style, row, col);
+            }
+        }
+        // the cells may have been drawn over the outline
+        self.outline_selection(view, &layout, &palette);
+    }
+}
+
+/// Renders an SVG document. Colours are passed through as given.
+pub struct SvgRenderer {
+    pub width: u32,
+    pub height: u32,
+    pub document: String,
+}
+
+impl SvgRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        SvgRenderer {
+            width,
+            height,
+            document: String::new(),
+        }
+    }
+}
+
+impl Renderer for SvgRenderer {
+    fn render(&mut self, view: &View) {
+        let layout = Layout::new(view, self.width, self.height);
+        let escape = |color: String| color.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;");
+        let mut document = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+            self.width, self.height, self.width, self.height
+        );
+        document.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+            layout.x_offset - layout.border_width,
+            layout.y_offset - layout.border_width,
+            layout.grid_width + 2.0 * layout.border_width,
+            layout.grid_height + 2.0 * layout.border_width,
+            escape(view.config.get_line_color())
+        ));
+        if !view.config.lines_enabled {
+            document.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                layout.x_offset,
+                layout.y_offset,
+                layout.grid_width,
+                layout.grid_height,
+                escape(view.config.get_cell_dead_color())
+            ));
+        }
+        let major_lines = layout.major_lines(view);
+        if !major_lines.is_empty() {
+            document.push_str(&format!("<g fill=\"{}\">\n", escape(view.config.get_major_line_color())));
+            for (x, y, width, height) in major_lines {
+                document.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n",
+                    x, y, width, height
+                ));
+            }
+            document.push_str("</g>\n");
+        }
+
+        // one group per state so each cell only needs its position. Shaped
+        // alive cells are drawn over a dead square.
+        let style = CellStyle::new(view.config, &layout);
+        let plain = style.is_plain(&layout);
+        document.push_str(&format!("<g fill=\"{}\">\n", escape(view.config.get_cell_dead_color())));
+        for row in 0..view.visible_rows {
+            for col in 0..view.visible_columns {
+                if plain && view.visible_cell(row, col) == Cell::Alive {
+                    continue;
+                }
+                let (x, y) = layout.cell_origin(row, col);
+                document.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n",
+                    x, y, layout.cell_size, layout.cell_size
+                ));
+            }
+        }
+        document.push_str("</g>\n");
+        // the glow is applied to each shape, with the filter region set to the
+        // square of its cell so that, as on the canvas, it does not spill onto
+        // the lines or neighbouring cells. Shapes are centred, so the square
+        // is the same in the units of any shape's bounding box.
+        let glow = if style.glow > 0.0 {
+            let margin = (layout.cell_size / 2.0 - style.half) / (2.0 * style.half);
+            document.push_str(&format!(
+                "<filter id=\"glow\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"><feDropShadow dx=\"0\" dy=\"0\" stdDeviation=\"{}\" flood-color=\"{}\"/></filter>\n",
+                -margin,
+                -margin,
+                1.0 + 2.0 * margin,
+                1.0 + 2.0 * margin,
+                style.glow / 2.0,
+                escape(view.config.get_glow_color())
+            ));
+            " filter=\"url(#glow)\""
+        } else {
+            ""
+        };
+        document.push_str(&format!("<g fill=\"{}\">\n", escape(view.config.get_cell_alive_color())));
+        for row in 0..view.visible_rows {
+            for col in 0..view.visible_columns {
+                if view.visible_cell(row, col) != Cell::Alive {
+                    continue;
+                }
+                let (x, y) = layout.cell_origin(row, col);
+                let (center_x, center_y) = (x + layout.cell_size / 2.0, y + layout.cell_size / 2.0);
+                let half = style.half;
+                let element = match style.shape {
+                    _ if plain => format!(
+                        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>",
+                        x, y, layout.cell_size, layout.cell_size
+                    ),
+                    CellShape::Square | CellShape::Rounded => format!(
+                        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"{}/>",
+                        center_x - half,
+                        center_y - half,
+                        2.0 * half,
+                        2.0 * half,
+                        if style.shape == CellShape::Rounded { style.corner_radius } else { 0.0 },
+                        glow
+                    ),
+                    CellShape::Circle => format!(
+                        "<circle cx=\"{}\" cy=\"{}\" r=\"{}\"{}/>",
+                        center_x, center_y, half, glow
+                    ),
+                    CellShape::Diamond => format!(
+                        "<polygon points=\"{},{} {},{} {},{} {},{}\"{}/>",
+                        center_x, center_y - half,
+                        center_x + half, center_y,
+                        center_x, center_y + half,
+                        center_x - half, center_y,
+                        glow
+                    ),
+                };
+                document.push_str(&element);
+                document.push('\n');
+            }
+        }
+        document.push_str("</g>\n");
+        if let Some(outline) = layout.selection_outline(view) {
+            document.push_str(&format!("<g fill=\"{}\">\n", escape(view.config.get_selection_color())));
+            for (x, y, width, height) in outline.iter() {
+                document.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n",
+                    x, y, width, height
+                ));
+            }
+            document.push_str("</g>\n");
+        }
+        document.push_str("</svg>\n");
+        self.document = document;
+    }
+}
+
+/// Renders to a canvas through its 2D context.
+#[cfg(feature = "web")]
+pub struct CanvasRenderer {
+    pub context: web_sys::CanvasRenderingContext2d,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[cfg(feature = "web")]
+impl CanvasRenderer {
+    pub fn new(canvas: &web_sys::HtmlCanvasElement) -> Self {
+        use wasm_bindgen::JsCast;
+        let context = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+            .unwrap();
+        CanvasRenderer {
+            context,
+            width: canvas.width(),
+            height: canvas.height(),
+        }
+    }
+}
+
+#[cfg(feature = "web")]
+impl Renderer for CanvasRenderer {
+    fn render(&mut self, view: &View) {
+        let context = &self.context;
+        let layout = Layout::new(view, self.width, self.height);
+        let line_width = layout.line_width;
+        let border_width = layout.border_width;
+        let cell_size = layout.cell_size;
+        let (x_offset, y_offset) = (layout.x_offset, layout.y_offset);
+        let (visible_grid_width, visible_grid_height) = (layout.grid_width, layout.grid_height);
+
+        // the grid may have moved or shrunk since the last frame
+        context.clear_rect(0.0, 0.0, self.width as f64, self.height as f64);
+
+        // draw border
+        context.begin_path();
+        context.set_stroke_style_str(&view.config.get_line_color());
+        context.set_line_width(border_width);
+        context.rect(
+            x_offset - border_width / 2.0,
+            y_offset - border_width / 2.0,
+            visible_grid_width + border_width,
+            visible_grid_height + border_width
+        );
+        context.stroke();
+
+        // draw dividing lines, or just the major lines
+        if !view.config.lines_enabled {
+            context.set_fill_style_str(&view.config.get_cell_dead_color());
+            context.fill_rect(x_offset, y_offset, visible_grid_width, visible_grid_height);
+        }
+        context.begin_path();
+        context.set_stroke_style_str(&view.config.get_line_color());
+        context.set_line_width(line_width);
+        for i in (1..(view.visible_columns)).filter(|_| view.config.lines_enabled) {
+            context.move_to(
+                x_offset + line_width / 2.0 + i as f64 * (cell_size + line_width) - line_width,
+                y_offset,
+            );
+            context.line_to(
+                x_offset + line_width / 2.0 + i as f64 * (cell_size + line_width) - line_width,
+                y_offset + visible_grid_height,
+            );
+        }
+        for i in (1..(view.visible_rows)).filter(|_| view.config.lines_enabled) {
+            context.move_to(
+                x_offset,
+                y_offset + line_width / 2.0 + i as f64 * (cell_size + line_width) - line_width,
+            );
+            context.line_to(
+                x_offset + visible_grid_width,
+                y_offset + line_width / 2.0 + i as f64 * (cell_size + line_width) - line_width,
+            );
+        }
+        context.stroke();
+        context.set_fill_style_str(&view.config.get_major_line_color());
+        for (x, y, width, height) in layout.major_lines(view) {
+            context.fill_rect(x, y, width, height);
+        }
+
+        if layout.is_sub_cell() {
+            self.shade_sub_cells(view, &layout);
+        } else {
+            let cells = (0..view.visible_columns).flat_map(|col| (0..view.visible_rows).map(move |row| (row, col)));
+            self.fill_cells(view, &layout, cells);
+        }
+        self.outline_selection(view, &layout);
+    }
+
+    fn render_cells(&mut self, view: &View, cells: &[(u32, u32)]) {
+        let layout = Layout::new(view, self.width, self.height);
+        if layout.is_sub_cell() {
+            // pixels are shared with unchanged cells
+            self.render(view);
+            return;
+        }
+        self.fill_cells(view, &layout, cells.iter().copied());
+        // the cells may have been drawn over the outline
+        self.outline_selection(view, &layout);
+    }
+}
+
+#[cfg(feature = "web")]
+impl CanvasRenderer {
+    /// Fill visible cells with the colour of their state. All dead cells are
+    /// drawn and then all alive cells, so the fill style only changes once.
+    /// Shaped alive cells are drawn over a dead square.
+    fn fill_cells<I: Iterator<Item = (u32, u32)> + Clone>(&self, view: &View, layout: &Layout, cells: I) {
+        let context = &self.context;
+        let style = CellStyle::new(view.config, layout);
+        let plain = style.is_plain(layout);
+
+        context.set_fill_style_str(&view.config.get_cell_dead_color());
+        for (row, col) in cells.clone() {
+            if plain && view.visible_cell(row, col) == Cell::Alive {
+                continue;
+            }
+            let (x, y) = layout.cell_origin(row, col);
+            context.fill_rect(x, y, layout.cell_size, layout.cell_size);
+        }
+
+        context.set_fill_style_str(&view.config.get_cell_alive_color());
+        if style.glow > 0.0 {
+            context.set_shadow_color(&view.config.get_glow_color());
+            context.set_shadow_blur(style.glow);
+        }
+        for (row, col) in cells {
+            if view.visible_cell(row, col) != Cell::Alive {
+                continue;
+            }
+            let (x, y) = layout.cell_origin(row, col);
+            if plain {
+                context.fill_rect(x, y, layout.cell_size, layout.cell_size);
+            } else {
+                self.fill_shape(layout, &style, x, y);
+            }
+        }
+        context.set_shadow_blur(0.0);
+    }
+
+    /// Fill the shape of an alive cell whose square has its top left corner
+    /// at `(x, y)`. The glow is clipped to the square, so that repainting a
+    /// cell never leaves glow on its neighbours.
+    fn fill_shape(&self, layout: &Layout, style: &CellStyle, x: f64, y: f64) {
+        let context = &self.context;
+        if style.glow > 0.0 {
+            context.save();
+            context.begin_path();
+            context.rect(x, y, layout.cell_size, layout.cell_size);
+            context.clip();
+        }
+        let (center_x, center_y) = (x + layout.cell_size / 2.0, y + layout.cell_size / 2.0);
+        let half = style.half;
+        let (left, top, right, bottom) = (center_x - half, center_y - half, center_x + half, center_y + half);
+        context.begin_path();
+        match style.shape {
+            CellShape::Square => context.rect(left, top, 2.0 * half, 2.0 * half),
+            CellShape::Circle => context
+                .arc(center_x, center_y, half, 0.0, std::f64::consts::TAU)
+                .expect("cannot draw cell"),
+            CellShape::Rounded => {
+                let radius = style.corner_radius;
+                context.move_to(left + radius, top);
+                for &(x1, y1, x2, y2) in &[
+                    (right, top, right, bottom),
+                    (right, bottom, left, bottom),
+                    (left, bottom, left, top),
+                    (left, top, right, top),
+                ] {
+                    context.arc_to(x1, y1, x2, y2, radius).expect("cannot draw cell");
+                }
+            },
+            CellShape::Diamond => {
+                context.move_to(center_x, top);
+                context.line_to(right, center_y);
+                context.line_to(center_x, bottom);
+                context.line_to(left, center_y);
+            },
+        }
+        context.close_path();
+        context.fill();
+        if style.glow > 0.0 {
+            context.restore();
+        }
+    }
+
+    fn outline_selection(&self, view: &View, layout: &Layout) {
+        if let Some(outline) = layout.selection_outline(view) {
+            self.context.set_fill_style_str(&view.config.get_selection_color());
+            for &(x, y, width, height) in outline.iter() {
+                self.context.fill_rect(x, y, width, height);
+            }
+        }
+    }
+
+    /// Draw cells smaller than a pixel: the dead colour over the whole grid,
+    /// then every alive cell. `Any` snaps alive cells out to whole pixels,
+    /// while `Density` leaves them fractional so that the canvas blends them
+    /// with the dead colour.
+    fn shade_sub_cells(&self, view: &View, layout: &Layout) {
+        let context = &self.context;
+        context.set_fill_style_str(&view.config.get_cell_dead_color());
+        context.fill_rect(layout.x_offset, layout.y_offset, layout.grid_width, layout.grid_height);
+        context.set_fill_style_str(&view.config.get_cell_alive_color());
+        let snap = view.config.get_sub_cell_shading() == SubCellShading::Any;
+        for row in 0..view.visible_rows {
+            for col in 0..view.visible_columns {
+                if view.visible_cell(row, col) != Cell::Alive {
+                    continue;
+                }
+                let (x, y) = layout.cell_origin(row, col);
+                if snap {
+                    context.fill_rect(x.floor(), y.floor(), 1.0, 1.0);
+                } else {
+                    context.fill_rect(x, y, layout.cell_size, layout.cell_size);
+                }
+            }
+        }
+    }
+}
+
+/// Renders into a pixel buffer and copies it to a canvas with a single
+/// `put_image_data` call. The buffer is kept between frames.
+#[cfg(feature = "web")]
+pub struct ImageDataRenderer {
+    pub context: web_sys::CanvasRenderingContext2d,
+    pub framebuffer: FramebufferRenderer,
+}
+
+#[cfg(feature = "web")]
+impl ImageDataRenderer {
+    pub fn new(canvas: &web_sys::HtmlCanvasElement) -> Self {
+        ImageDataRenderer {
+            context: CanvasRenderer::new(canvas).context,
+            framebuffer: FramebufferRenderer::new(canvas.width(), canvas.height()),
+        }
+    }
+}
+
+#[cfg(feature = "web")]
+impl Renderer for ImageDataRenderer {
+    fn render(&mut self, view: &View) {
+        self.framebuffer.render(view);
+        let image = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
+            wasm_bindgen::Clamped(&self.framebuffer.pixels),
+            self.framebuffer.width,
+            self.framebuffer.height,
+        )
+        .expect("cannot create image data");
+        self.context.put_image_data(&image, 0.0, 0.0).expect("cannot draw image data");
+    }
+
+    /// Only the bounding box of the repainted cells is copied to the canvas.
+    fn render_cells(&mut self, view: &View, cells: &[(u32, u32)]) {
+        if cells.is_empty() {
+            return;
+        }
+        self.framebuffer.render_cells(view, cells);
+        let layout = Layout::new(view, self.framebuffer.width, self.framebuffer.height);
+        let corners = cells.iter().map(|&(row, col)| layout.cell_origin(row, col));
+        let left = corners.clone().map(|(x, _)| x).fold(f64::INFINITY, f64::min);
+        let top = corners.clone().map(|(_, y)| y).fold(f64::INFINITY, f64::min);
+        let right = corners.clone().map(|(x, _)| x).fold(f64::NEG_INFINITY, f64::max) + layout.cell_size;
+        let bottom = corners.map(|(_, y)| y).fold(f64::NEG_INFINITY, f64::max) + layout.cell_size;
+        let clip = |value: f64, max: u32| value.max(0.0).min(max as f64) as u32;
+        let (left, right) = (clip(left.floor(), self.framebuffer.width), clip(right.ceil(), self.framebuffer.width));
+        let (top, bottom) = (clip(top.floor(), self.framebuffer.height), clip(bottom.ceil(), self.framebuffer.height));
+        if left == right || top == bottom {
+            return;
+        }
+        let pixels = self.framebuffer.region(left, top, right - left, bottom - top);
+        let image = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
+            wasm_bindgen::Clamped(&pixels),
+            right - left,
+            bottom - top,
+        )
+        .expect("cannot create image data");
+        self.context
+            .put_image_data(&image, left as f64, top as f64)
+            .expect("cannot draw image data");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALIVE: Rgba = Rgba(0, 0, 0, 255);
+    const DEAD: Rgba = Rgba(255, 255, 255, 255);
+    const LINE: Rgba = Rgba(255, 0, 0, 255);
+
+    fn config() -> UniverseConfig {
+        UniverseConfig::new()
+            .set_line_color("red")
+            .set_cell_alive_color("black")
+            .set_cell_dead_color("white")
+    }
+
+    fn view<'a>(config: &'a UniverseConfig, cells: &'a [Cell], cell_size: f64) -> View<'a> {
+        View {
+            width: 2,
+            height: 2,
+            cells,
+            visible_rows: 2,
+            visible_columns: 2,
+            visible_row_start_position: 0,
+            visible_column_start_position: 0,
+            cell_size,
+            selection: None,
+            config,
+        }
+    }
+
+    fn render(view: &View, size: u32) -> FramebufferRenderer {
+        let mut renderer = FramebufferRenderer::new(size, size);
+        renderer.render(view);
+        renderer
+    }
+
+    #[test]
+    fn parses_css_colours() {
+        assert_eq!(Rgba::parse("#f00"), Some(Rgba(255, 0, 0, 255)));
+        assert_eq!(Rgba::parse(" #00ff00 "), Some(Rgba(0, 255, 0, 255)));
+        assert_eq!(Rgba::parse("#0000ff80"), Some(Rgba(0, 0, 255, 128)));
+        assert_eq!(Rgba::parse("Navy"), Some(Rgba(0, 0, 128, 255)));
+        assert_eq!(Rgba::parse("transparent"), Some(Rgba::TRANSPARENT));
+        assert_eq!(Rgba::parse("#0f08"), Some(Rgba(0, 255, 0, 136)));
+        assert_eq!(Rgba::parse("RebeccaPurple"), Some(Rgba(102, 51, 153, 255)));
+        assert_eq!(Rgba::parse("#12"), None);
+        assert_eq!(Rgba::parse("#ggg"), None);
+        assert_eq!(Rgba::parse("bogus"), None);
+    }
+
+    #[test]
+    fn parses_css_colour_functions() {
+        assert_eq!(Rgba::parse("rgb(255, 128, 0)"), Some(Rgba(255, 128, 0, 255)));
+        assert_eq!(Rgba::parse("rgba(0,0,255,0.5)"), Some(Rgba(0, 0, 255, 128)));
+        assert_eq!(Rgba::parse("rgb(100% 0% 50% / 25%)"), Some(Rgba(255, 0, 128, 64)));
+        assert_eq!(Rgba::parse("RGB(300, -5, 0)"), Some(Rgba(255, 0, 0, 255)));
+        assert_eq!(Rgba::parse("hsl(0, 100%, 50%)"), Some(Rgba(255, 0, 0, 255)));
+        assert_eq!(Rgba::parse("hsl(120deg 100% 25%)"), Some(Rgba(0, 128, 0, 255)));
+        assert_eq!(Rgba::parse("hsla(240, 100%, 50%, 0.5)"), Some(Rgba(0, 0, 255, 128)));
+        assert_eq!(Rgba::parse("hsl(-60, 100%, 50%)"), Some(Rgba(255, 0, 255, 255)));
+        assert_eq!(Rgba::parse("hsl(0, 0%, 100%)"), Some(Rgba(255, 255, 255, 255)));
+        assert_eq!(Rgba::parse("rgb(1, 2)"), None);
+        assert_eq!(Rgba::parse("rgb(1, 2, x)"), None);
+        assert_eq!(Rgba::parse("hsl(0, 100, 50)"), None);
+        assert_eq!(Rgba::parse("lab(50% 40 59)"), None);
+    }
+
+    #[test]
+    fn named_colours_are_sorted() {
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(NAMED_COLORS.len(), 148);
+    }
+
+    #[test]
+    fn draws_cells_lines_and_border() {
+        let config = config();
+        let cells = [Cell::Alive, Cell::Dead, Cell::Dead, Cell::Alive];
+        // two 4 pixel cells and a 2 pixel line make a 10 pixel grid, which
+        // sits 4 pixels in from the edges of an 18 pixel surface
+        let renderer = render(&view(&config, &cells, 4.0), 18);
+        assert_eq!(renderer.pixel(0, 0), LINE);
+        assert_eq!(renderer.pixel(5, 5), ALIVE);
+        assert_eq!(renderer.pixel(8, 5), LINE);
+        assert_eq!(renderer.pixel(11, 5), DEAD);
+        assert_eq!(renderer.pixel(5, 11), DEAD);
+        assert_eq!(renderer.pixel(13, 13), ALIVE);
+    }
+
+    #[test]
+    fn locates_the_cell_under_a_point() {
+        let config = config();
+        let cells = [Cell::Dead; 4];
+        // as above, cells span 4 to 8 and 10 to 14 pixels in each direction
+        let layout = Layout::new(&view(&config, &cells, 4.0), 18, 18);
+        assert_eq!(layout.visible_cell_at(4.0, 4.0), Some((0, 0)));
+        assert_eq!(layout.visible_cell_at(7.9, 13.9), Some((1, 0)));
+        assert_eq!(layout.visible_cell_at(10.0, 5.0), Some((0, 1)));
+        // the line between cells, the border and beyond the grid
+        assert_eq!(layout.visible_cell_at(8.5, 5.0), None);
+        assert_eq!(layout.visible_cell_at(5.0, 9.9), None);
+        assert_eq!(layout.visible_cell_at(3.5, 5.0), None);
+        assert_eq!(layout.visible_cell_at(14.0, 5.0), None);
+    }
+
+    #[test]
+    fn copies_a_region_row_by_row() {
+        let mut renderer = FramebufferRenderer::new(4, 3);
+        renderer.fill_rect(1.0, 1.0, 2.0, 2.0, LINE);
+        let region = renderer.region(1, 0, 2, 2);
+        assert_eq!(region.len(), 2 * 2 * 4);
+        assert_eq!(region[..8], [0; 8]);
+        assert_eq!(region[8..], [255, 0, 0, 255, 255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn repainting_cells_matches_a_full_render() {
+        let config = config().set_cell_shape(CellShape::Circle).set_glow(2, "blue");
+        let before = [Cell::Alive, Cell::Dead, Cell::Dead, Cell::Alive];
+        let after = [Cell::Dead, Cell::Alive, Cell::Dead, Cell::Alive];
+        let mut renderer = render(&view(&config, &before, 8.0), 30);
+        renderer.render_cells(&view(&config, &after, 8.0), &[(0, 0), (0, 1)]);
+        assert_eq!(renderer.pixels, render(&view(&config, &after, 8.0), 30).pixels);
+    }
+
+    #[test]
+    fn repainting_sub_pixel_cells_matches_a_full_render() {
+        let config = config().set_sub_cell_shading(SubCellShading::Density);
+        let before = vec![Cell::Dead; 100];
+        let mut after = before.clone();
+        // at 0.3 pixels a cell, the cell in column 3 spans 0.9 to 1.2 and so
+        // straddles the first two pixels
+        let changed = [(0, 3), (3, 3), (5, 6), (9, 9)];
+        for &(row, col) in changed.iter() {
+            after[row as usize * 10 + col as usize] = Cell::Alive;
+        }
+        let view = |cells| View {
+            width: 10,
+            height: 10,
+            visible_rows: 10,
+            visible_columns: 10,
+            ..view(&config, cells, 0.3)
+        };
+        let mut renderer = render(&view(&before), 8);
+        renderer.render_cells(&view(&after), &changed);
+        assert_eq!(renderer.pixels, render(&view(&after), 8).pixels);
+    }
+
+    #[test]
+    fn shapes_leave_the_corners_of_the_cell_dead() {
+        let config = config().set_cell_shape(CellShape::Circle);
+        let cells = [Cell::Alive; 4];
+        // the top left cell covers pixels 6 to 13
+        let renderer = render(&view(&config, &cells, 8.0), 30);
+        assert_eq!(renderer.pixel(10, 10), ALIVE);
+        assert_eq!(renderer.pixel(6, 6), DEAD);
+    }
+
+    #[test]
+    fn svg_glow_is_clipped_to_the_cell() {
+        let config = config().set_cell_shape(CellShape::Circle).set_cell_gap(1).set_glow(2, "blue");
+        let cells = [Cell::Alive, Cell::Dead, Cell::Dead, Cell::Dead];
+        let mut renderer = SvgRenderer::new(30, 30);
+        renderer.render(&view(&config, &cells, 8.0));
+        // the circle is 6 pixels across in an 8 pixel cell, so the region
+        // reaches one pixel, or a sixth of the circle, past each side
+        let margin = 1.0 / 6.0;
+        let region = format!(
+            "<filter id=\"glow\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\">",
+            -margin, -margin, 1.0 + 2.0 * margin, 1.0 + 2.0 * margin
+        );
+        assert!(renderer.document.contains(&region));
+        assert!(renderer.document.contains("<circle cx=\"10\" cy=\"10\" r=\"3\" filter=\"url(#glow)\"/>"));
+    }
+
+    #[test]
+    fn shades_sub_cell_pixels_by_density() {
+        let config = config().set_sub_cell_shading(SubCellShading::Density);
+        let cells = [Cell::Alive, Cell::Dead, Cell::Dead, Cell::Dead];
+        // all four cells share the one pixel
+        let renderer = render(&view(&config, &cells, 0.5), 1);
+        assert_eq!(renderer.pixel(0, 0), DEAD.mix(ALIVE, 0.25));
+
+        let config = config.set_sub_cell_shading(SubCellShading::Any);
+        let renderer = render(&view(&config, &cells, 0.5), 1);
+        assert_eq!(renderer.pixel(0, 0), ALIVE);
+    }
+}
@@ -1,6 +1,14 @@
-use itertools::Itertools;
+use std::fmt;
+use std::io;
+
 use regex::Regex;
 
+use crate::display::GridDisplay;
+use crate::life105;
+use crate::life106;
+use crate::macrocell;
+use crate::pattern::Pattern;
+use crate::plaintext;
 use crate::universe::Cell;
 
 pub struct RleData {
@@ -9,278 +17,622 @@ pub struct RleData {
     pub cells: Vec<Cell>,
 }
 
-#[derive(Eq, PartialEq, Debug)]
-enum RleCharacter {
-    Number,
-    NewLine,
-    Dead,
-    Alive,
-    End,
+/// The pattern file formats that can be loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Rle,
+    Plaintext,
+    Life105,
+    Life106,
+    Macrocell,
 }
 
-#[derive(Debug, Copy, Clone)]
-enum RleToken {
-    Number(u32),
-    NewLine,
-    Dead,
-    Alive,
-    End,
+/// Detect the format of a pattern string from its contents.
+pub fn detect_format(pattern: &str) -> Format {
+    let first_line = pattern.lines().map(|line| line.trim()).find(|line| !line.is_empty());
+    match first_line {
+        Some(line) if line.starts_with(life105::HEADER) => Format::Life105,
+        Some(line) if line.starts_with(life106::HEADER) => Format::Life106,
+        Some(line) if line.starts_with(macrocell::HEADER) => Format::Macrocell,
+        _ if plaintext::is_plaintext(pattern) => Format::Plaintext,
+        _ => Format::Rle,
+    }
+}
+
+/// Load a pattern in any of the supported formats, which is detected from the
+/// contents of the string.
+pub fn load_any(pattern: &str) -> Result<Pattern, String> {
+    match detect_format(pattern) {
+        Format::Rle => load_pattern(pattern),
+        Format::Plaintext => plaintext::load(pattern),
+        Format::Life105 => life105::load(pattern),
+        Format::Life106 => life106::load(pattern),
+        Format::Macrocell => {
+            let macrocell = macrocell::load(pattern)?;
+            let fits = match macrocell.bounding_box() {
+                Some((_, _, width, height)) => {
+                    width <= u32::MAX as u64 && height <= u32::MAX as u64 && macrocell.population() <= MAX_EXPANDED_CELLS
+                },
+                None => true,
+            };
+            if !fits {
+                return Err(String::from("macrocell pattern is too large to expand, load a window of it instead"));
+            }
+            Ok(macrocell.to_pattern())
+        },
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
-enum RleElement {
+/// The most alive cells `load_any` will expand a macrocell into.
+const MAX_EXPANDED_CELLS: u64 = 1 << 24;
+
+/// Copy a `cells_width` x `cells_height` block of cells into the centre of a
+/// `width` x `height` grid. Any part of the block that does not fit is
+/// clipped.
+pub fn embed_centered(cells: &[Cell], cells_width: u32, cells_height: u32, width: u32, height: u32) -> Vec<Cell> {
+    let mut grid = vec![Cell::Dead; (width * height) as usize];
+    let row_offset = height as i64 / 2 - cells_height as i64 / 2;
+    let col_offset = width as i64 / 2 - cells_width as i64 / 2;
+    for row in 0..cells_height {
+        for col in 0..cells_width {
+            let target_row = row as i64 + row_offset;
+            let target_col = col as i64 + col_offset;
+            if target_row < 0 || target_col < 0 || target_row >= height as i64 || target_col >= width as i64 {
+                continue;
+            }
+            grid[(target_row * width as i64 + target_col) as usize] = cells[(row * cells_width + col) as usize];
+        }
+    }
+    grid
+}
+
+impl RleData {
+    /// Copy the pattern into the centre of a `width` x `height` grid.
+    pub fn embed(&self, width: u32, height: u32) -> Vec<Cell> {
+        embed_centered(&self.cells, self.width, self.height, width, height)
+    }
+
+    /// Render the cells as text.
+    pub fn display(&self) -> GridDisplay<'_> {
+        GridDisplay::new(self.width, self.height, &self.cells)
+    }
+}
+
+impl fmt::Display for RleData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display().fmt(f)
+    }
+}
+
+/// Size of the buffer used when reading RLE from an `io::Read`.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// An item of RLE pattern data, emitted by `RleParser` in file order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RleEvent {
+    /// The `x = .., y = ..` header line.
+    Header { width: u32, height: u32 },
+    /// `count` cells in the given state, continuing the current row.
+    Run { state: Cell, count: u32 },
+    /// End the current row and skip `count - 1` empty rows.
     NewLine(u32),
-    Dead(u32),
-    Alive(u32),
+    /// The end of the pattern (`!`, or the end of input).
     End,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ParserState {
+    LineStart,
+    Comment,
+    Header,
+    Body,
+    Done,
+}
 
-pub fn load(rle_string: &str) -> RleData {
-    let re = Regex::new(r"x\s*=\s*(\d+)\s*,\s*y\s*=\s*(\d+)")
-        .expect("Regex failed to compile");
+/// A streaming RLE parser. Input is fed in chunks of any size, which may
+/// split lines or numbers, and events are passed to a callback as soon as
+/// they are complete. Nothing but the header line is buffered. After an error
+/// the rest of the input is ignored.
+pub struct RleParser {
+    state: ParserState,
+    header: Vec<u8>,
+    count: Option<u32>,
+}
 
-    // The iterator starts here
-    let mut iterator_to_xy_line = rle_string
-        .lines()
-        .map(|line| line.trim_left())
-        // remove comment lines
-        .filter(|line| ! line.starts_with("#"))
-        // remove empty lines
-        .filter(|line| line.trim().len() != 0);
-    
-    let xy_line = iterator_to_xy_line.next().expect("cannot read rle source");
-
-    // collect the width and height
-    let cap = re.captures(xy_line).expect("Rle string input is not valid");
-    let x = String::from(cap.get(1).unwrap().as_str());
-    let y = String::from(cap.get(2).unwrap().as_str());
-    let width_from_file: u32 = x.parse().expect(&format!("error parsing x (`{}`)", x));
-    let height_from_file: u32 = y.parse().expect(&format!("error parsing y (`{}`)", y));
-
-    let mut cells = iterator_to_xy_line
-        // Convert to chars
-        .flat_map(|line| line.chars())
-        .into_iter()
-        // Identify adjacent tokens and group them together
-        .group_by(|c| match *c {
-            'b' => RleCharacter::Dead,
-            'o' => RleCharacter::Alive,
-            '$' => RleCharacter::NewLine,
-            '!' => RleCharacter::End,
-             _  => RleCharacter::Number,
-        })
-        .into_iter()
-        // Convert characters into tokens (particularly Number)
-        .map(|(key, group)| {
-            match key {
-                RleCharacter::Dead => RleToken::Dead,
-                RleCharacter::Alive => RleToken::Alive,
-                RleCharacter::NewLine => RleToken::NewLine,
-                RleCharacter::End => RleToken::End,
-                RleCharacter::Number => RleToken::Number(group.collect::<String>().parse::<u32>().unwrap()),
+impl Default for RleParser {
+    fn default() -> Self {
+        RleParser::new()
+    }
+}
+
+impl RleParser {
+    pub fn new() -> Self {
+        RleParser {
+            state: ParserState::LineStart,
+            header: Vec::new(),
+            count: None,
+        }
+    }
+
+    /// Parse the next chunk of input.
+    pub fn feed<F: FnMut(RleEvent)>(&mut self, chunk: &[u8], f: &mut F) -> Result<(), String> {
+        let result = self.feed_bytes(chunk, f);
+        if result.is_err() {
+            self.state = ParserState::Done;
+        }
+        result
+    }
+
+    fn feed_bytes<F: FnMut(RleEvent)>(&mut self, chunk: &[u8], f: &mut F) -> Result<(), String> {
+        for &byte in chunk {
+            match self.state {
+                ParserState::LineStart => match byte {
+                    // comment lines
+                    b'#' => self.state = ParserState::Comment,
+                    // empty lines and leading whitespace
+                    b' ' | b'\t' | b'\r' | b'\n' => (),
+                    _ => {
+                        self.header.push(byte);
+                        self.state = ParserState::Header;
+                    },
+                },
+                ParserState::Comment => {
+                    if byte == b'\n' {
+                        self.state = ParserState::LineStart;
+                    }
+                },
+                ParserState::Header => {
+                    if byte == b'\n' {
+                        self.finish_header(f)?;
+                    } else {
+                        self.header.push(byte);
+                    }
+                },
+                ParserState::Body => self.feed_body(byte, f)?,
+                ParserState::Done => break,
             }
-        })
-        // A super simple parser
-        .batching(|it| {
-            match it.next() {
-                None => None,
-                Some(RleToken::Dead) => Some(RleElement::Dead(1)),
-                Some(RleToken::Alive) => Some(RleElement::Alive(1)),
-                Some(RleToken::NewLine) => Some(RleElement::NewLine(1)),
-                Some(RleToken::End) => Some(RleElement::End),
-                Some(RleToken::Number(x)) => {
-                    match it.next() {
-                        None => None,
-                        Some(RleToken::Dead) => Some(RleElement::Dead(x)),
-                        Some(RleToken::Alive) => Some(RleElement::Alive(x)),
-                        Some(RleToken::NewLine) => Some(RleElement::NewLine(x)),
-                        Some(RleToken::Number(_)) => panic!("have a number after a number."),
-                        Some(RleToken::End) => panic!("cannot have multiple ends"),
+        }
+        Ok(())
+    }
+
+    /// Signal the end of input, ending the pattern if `!` was missing.
+    pub fn finish<F: FnMut(RleEvent)>(&mut self, f: &mut F) -> Result<(), String> {
+        match self.state {
+            ParserState::LineStart | ParserState::Comment => {
+                self.state = ParserState::Done;
+                Err(String::from("rle input has no header line"))
+            },
+            ParserState::Header => {
+                if let Err(err) = self.finish_header(f) {
+                    self.state = ParserState::Done;
+                    return Err(err);
+                }
+                self.finish(f)
+            },
+            ParserState::Body => {
+                self.state = ParserState::Done;
+                if self.count.is_some() {
+                    return Err(String::from("rle input ends with a count"));
+                }
+                f(RleEvent::End);
+                Ok(())
+            },
+            ParserState::Done => Ok(()),
+        }
+    }
+
+    fn finish_header<F: FnMut(RleEvent)>(&mut self, f: &mut F) -> Result<(), String> {
+        let re = Regex::new(r"x\s*=\s*(\d+)\s*,\s*y\s*=\s*(\d+)")
+            .expect("Regex failed to compile");
+        let xy_line = String::from_utf8_lossy(&self.header);
+        let cap = re
+            .captures(&xy_line)
+            .ok_or_else(|| format!("invalid rle header line `{}`", xy_line.trim()))?;
+        let x = cap.get(1).unwrap().as_str();
+        let y = cap.get(2).unwrap().as_str();
+        let width = x.parse().map_err(|_| format!("error parsing x (`{}`)", x))?;
+        let height = y.parse().map_err(|_| format!("error parsing y (`{}`)", y))?;
+        self.header.clear();
+        self.state = ParserState::Body;
+        f(RleEvent::Header { width, height });
+        Ok(())
+    }
+
+    fn feed_body<F: FnMut(RleEvent)>(&mut self, byte: u8, f: &mut F) -> Result<(), String> {
+        match byte {
+            b'0'..=b'9' => {
+                let digit = (byte - b'0') as u32;
+                let count = self
+                    .count
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|count| count.checked_add(digit))
+                    .ok_or("rle run count is too large")?;
+                self.count = Some(count);
+            },
+            b'b' => {
+                let count = self.count.take().unwrap_or(1);
+                f(RleEvent::Run { state: Cell::Dead, count });
+            },
+            b'o' => {
+                let count = self.count.take().unwrap_or(1);
+                f(RleEvent::Run { state: Cell::Alive, count });
+            },
+            b'$' => {
+                let count = self.count.take().unwrap_or(1);
+                f(RleEvent::NewLine(count));
+            },
+            b'!' => {
+                if self.count.is_some() {
+                    return Err(String::from("cannot have a count before the end"));
+                }
+                self.state = ParserState::Done;
+                f(RleEvent::End);
+            },
+            b' ' | b'\t' | b'\r' | b'\n' => {
+                if self.count.is_some() && byte != b'\n' && byte != b'\r' {
+                    return Err(String::from("cannot have whitespace inside a run"));
+                }
+            },
+            _ => return Err(format!("invalid rle character `{}`", byte as char)),
+        }
+        Ok(())
+    }
+}
+
+/// Parse RLE from a byte slice, passing each event to `f`.
+pub fn parse<F: FnMut(RleEvent)>(bytes: &[u8], mut f: F) -> Result<(), String> {
+    let mut parser = RleParser::new();
+    parser.feed(bytes, &mut f)?;
+    parser.finish(&mut f)
+}
+
+/// Parse RLE from a reader in fixed size chunks, passing each event to `f`.
+/// Invalid RLE is reported as an `InvalidData` error.
+pub fn parse_reader<R: io::Read, F: FnMut(RleEvent)>(mut reader: R, mut f: F) -> io::Result<()> {
+    let mut parser = RleParser::new();
+    let mut buffer = vec![0u8; READ_CHUNK_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        parser.feed(&buffer[..read], &mut f).map_err(invalid_data)?;
+    }
+    parser.finish(&mut f).map_err(invalid_data)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The most cells `load` will allocate for a dense grid. Larger patterns can
+/// still be loaded sparsely with `load_pattern`.
+const MAX_GRID_CELLS: u32 = 1 << 28;
+
+/// Writes parsed runs straight into a dense grid. Cells beyond the declared
+/// width or height are dropped.
+struct GridBuilder {
+    data: RleData,
+    row: u32,
+    col: u32,
+    error: Option<String>,
+}
+
+impl GridBuilder {
+    fn new() -> Self {
+        GridBuilder {
+            data: RleData { width: 0, height: 0, cells: vec![] },
+            row: 0,
+            col: 0,
+            error: None,
+        }
+    }
+
+    fn handle(&mut self, event: RleEvent) {
+        match event {
+            RleEvent::Header { width, height } => match width.checked_mul(height) {
+                Some(count) if count <= MAX_GRID_CELLS => {
+                    self.data = RleData {
+                        width,
+                        height,
+                        cells: vec![Cell::Dead; count as usize],
+                    };
+                },
+                // runs are then dropped as lying outside a 0x0 grid
+                _ => self.error = Some(format!("pattern of {}x{} cells is too large to load", width, height)),
+            },
+            RleEvent::Run { state, count } => {
+                let end = self.col.saturating_add(count).min(self.data.width);
+                if state == Cell::Alive && self.row < self.data.height && self.col < end {
+                    let row_start = (self.row * self.data.width) as usize;
+                    for cell in &mut self.data.cells[row_start + self.col as usize..row_start + end as usize] {
+                        *cell = Cell::Alive;
                     }
                 }
-            }
-        })
-        // Split up by lines
-        .collect::<Vec<RleElement>>()
-        .into_iter()
-        .group_by(|key| match key {
-            RleElement::NewLine(_) | RleElement::End => false,
-            _ => true
-        })
-        .into_iter()
-        // Oranise into (line, newline_or_end)
-        .map(|(_, group)| {
-            group.collect::<Vec<RleElement>>()
-        })
-        .chunks(2)
-        .into_iter()
-        .map(|mut it| {
-            let line = it.next().expect("No next iterator");
-            let new_line_or_end = it
-                .next()
-                .expect("No next iterator")
-                .get(0)
-                .expect("Missing newline element")
-                .clone();
-            (line, new_line_or_end)
-        })
-        // Convert from RleElements to Vec<Cell>
-        .map(|(line, newline_or_end): (Vec<RleElement> ,RleElement)| {
-            let complete_line = construct_line(line, width_from_file);
-            let newlines = if let RleElement::NewLine(x) = newline_or_end {
-                vec![ Cell::Dead; ((x-1) * width_from_file) as usize]
-            } else { vec![] };
-            let mut section = Vec::new();
-            section.extend(complete_line);
-            section.extend(newlines);
-            section
-        })
-        .flatten()
-        // Expand
-        .collect::<Vec<Cell>>()
-    ;
-    cells.extend(vec![ Cell::Dead; (width_from_file * height_from_file) as usize - cells.len()]);
-
-    RleData {
-        width: width_from_file,
-        height: height_from_file,
-        cells,
+                self.col = self.col.saturating_add(count);
+            },
+            RleEvent::NewLine(count) => {
+                self.row = self.row.saturating_add(count);
+                self.col = 0;
+            },
+            RleEvent::End => (),
+        }
     }
+
+    fn finish(self) -> Result<RleData, String> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.data),
+        }
+    }
+}
+
+/// Collects the alive cells of parsed runs, so memory use follows the
+/// population rather than the area of the pattern. Cells beyond the declared
+/// width or height are dropped.
+struct PatternBuilder {
+    width: u32,
+    height: u32,
+    cells: Vec<(u32, u32)>,
+    row: u32,
+    col: u32,
 }
 
-fn construct_line(line: Vec<RleElement>, width: u32) -> Vec<Cell> {
-    let width = width as usize;
-    let mut line = line
-        .into_iter()
-        .map(|element| match element {
-            RleElement::Alive(x) => vec![ Cell::Alive; x as usize],
-            RleElement::Dead(x) => vec![ Cell::Dead; x as usize],
-            _ => panic!("invalid line"),
-        })
-        .flatten()
-        .collect::<Vec<Cell>>();
-    if line.len() < width {
-        let missing_cells = vec![ Cell::Dead; width as usize - line.len()];
-        line.extend(missing_cells);
-        line
-    } else {
-        line.into_iter().take(width).collect()
+impl PatternBuilder {
+    fn new() -> Self {
+        PatternBuilder {
+            width: 0,
+            height: 0,
+            cells: vec![],
+            row: 0,
+            col: 0,
+        }
+    }
+
+    fn handle(&mut self, event: RleEvent) {
+        match event {
+            RleEvent::Header { width, height } => {
+                self.width = width;
+                self.height = height;
+            },
+            RleEvent::Run { state, count } => {
+                let end = self.col.saturating_add(count).min(self.width);
+                if state == Cell::Alive && self.row < self.height {
+                    let row = self.row;
+                    self.cells.extend((self.col..end).map(|col| (col, row)));
+                }
+                self.col = self.col.saturating_add(count);
+            },
+            RleEvent::NewLine(count) => {
+                self.row = self.row.saturating_add(count);
+                self.col = 0;
+            },
+            RleEvent::End => (),
+        }
+    }
+
+    fn finish(self) -> Pattern {
+        Pattern::new(self.width, self.height, self.cells)
     }
 }
 
-pub fn load_spaceships(width: u32, height: u32) -> Vec<Cell> {
-    let raw_contents = include_str!("../spaceships.rle");
+/// Load a pattern in the RLE format into a dense grid.
+pub fn load(rle_string: &str) -> Result<RleData, String> {
+    let mut builder = GridBuilder::new();
+    parse(rle_string.as_bytes(), |event| builder.handle(event))?;
+    builder.finish()
+}
 
-    let xy_line = raw_contents
-        .lines()
-        .skip(1)
-        .next()
-        .expect("cannot read ship source.");
-    let x: String = xy_line.chars().skip(4).take(3).collect();
-    let width_from_file: u32 = x.parse().expect(&format!("x (`{}`) is not a number", x));
-    let y: String = xy_line.chars().skip(13).take(3).collect();
-    let height_from_file: u32 = y.parse().expect(&format!("y (`{}`) is not a number", y));
-    // unsafe {
-    //     log!("width_from_file: {}", width_from_file);
-    //     log!("height_from_file: {}", height_from_file);
-    //     log!("width: {}", width);
-    //     log!("height: {}", height);
-    // }
-
-    let width_of_grid = if width > width_from_file { width } else { width_from_file };
-    let height_of_grid = if height > height_from_file { height } else { height_from_file };
-    println!("width: {}, height: {}", width, height);
-
-    
-
-    // let coordinates_string: String = raw_contents
-    let mut stream = raw_contents
+/// Load a pattern in the RLE format from a reader, without holding the
+/// source text in memory.
+pub fn load_reader<R: io::Read>(reader: R) -> io::Result<RleData> {
+    let mut builder = GridBuilder::new();
+    parse_reader(reader, |event| builder.handle(event))?;
+    builder.finish().map_err(invalid_data)
+}
+
+/// Load a pattern in the RLE format as a sparse pattern.
+pub fn load_pattern(rle_string: &str) -> Result<Pattern, String> {
+    let mut builder = PatternBuilder::new();
+    parse(rle_string.as_bytes(), |event| builder.handle(event))?;
+    Ok(builder.finish())
+}
+
+/// Load a sparse pattern in the RLE format from a reader.
+pub fn load_pattern_reader<R: io::Read>(reader: R) -> io::Result<Pattern> {
+    let mut builder = PatternBuilder::new();
+    parse_reader(reader, |event| builder.handle(event))?;
+    Ok(builder.finish())
+}
+
+/// The longest line written by `save`.
+const SAVE_LINE_LENGTH: usize = 70;
+
+/// The rule given in the header line of an RLE pattern, if any.
+pub fn read_rule(rle_string: &str) -> Option<String> {
+    let re = Regex::new(r"rule\s*=\s*([^\s,]+)").expect("Regex failed to compile");
+    let header = rle_string
         .lines()
-        .skip(2)
-        .flat_map(|line| line.chars())
-        .collect::<Vec<char>>()
-        .into_iter()
-        // Identify adjacent tokens and group them together
-        .group_by(|c| match *c {
-            'b' => RleCharacter::Dead,
-            'o' => RleCharacter::Alive,
-            '$' => RleCharacter::NewLine,
-            '!' => RleCharacter::End,
-             _  => RleCharacter::Number,
-        })
-        .into_iter()
-        // Convert characters into tokens (particularly Number)
-        .map(|(key, group)| {
-            match key {
-                RleCharacter::Dead => RleToken::Dead,
-                RleCharacter::Alive => RleToken::Alive,
-                RleCharacter::NewLine => RleToken::NewLine,
-                RleCharacter::End => RleToken::End,
-                RleCharacter::Number => RleToken::Number(group.collect::<String>().parse::<u32>().unwrap()),
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+    re.captures(header).map(|cap| String::from(&cap[1]))
+}
+
+/// Save a pattern in the RLE format. The rule defaults to `B3/S23`.
+pub fn save(pattern: &Pattern, rule: Option<&str>) -> String {
+    fn run(count: u32, tag: char) -> String {
+        if count == 1 {
+            tag.to_string()
+        } else {
+            format!("{}{}", count, tag)
+        }
+    }
+
+    let mut items = Vec::new();
+    let mut row = 0;
+    for y in 0..pattern.height() {
+        let cells = pattern.row(y);
+        if cells.is_empty() {
+            continue;
+        }
+        if y > row {
+            items.push(run(y - row, '$'));
+        }
+        row = y;
+        let mut col = 0;
+        let mut start = 0;
+        while start < cells.len() {
+            // extend the run over consecutive alive cells
+            let mut end = start + 1;
+            while end < cells.len() && cells[end].0 == cells[end - 1].0 + 1 {
+                end += 1;
             }
-        })
-        // A super simple parser
-        .batching(|it| {
-            match it.next() {
-                None => None,
-                Some(RleToken::Dead) => Some(RleElement::Dead(1)),
-                Some(RleToken::Alive) => Some(RleElement::Alive(1)),
-                Some(RleToken::NewLine) => Some(RleElement::NewLine(1)),
-                Some(RleToken::End) => Some(RleElement::End),
-                Some(RleToken::Number(x)) => {
-                    match it.next() {
-                        None => None,
-                        Some(RleToken::Dead) => Some(RleElement::Dead(x)),
-                        Some(RleToken::Alive) => Some(RleElement::Alive(x)),
-                        Some(RleToken::NewLine) => Some(RleElement::NewLine(x)),
-                        Some(RleToken::Number(_)) => panic!("have a number after a number."),
-                        Some(RleToken::End) => panic!("cannot have multiple ends"),
-                    }
-                }
+            let x = cells[start].0;
+            if x > col {
+                items.push(run(x - col, 'b'));
             }
-        })
-        // Split up by lines
-        .collect::<Vec<RleElement>>()
-        .into_iter()
-        .group_by(|key| match key {
-            RleElement::NewLine(_) | RleElement::End => false,
-            _ => true
-        })
-        .into_iter()
-        // Oranise into (line, newline_or_end)
-        .map(|(_, group)| {
-            group.collect::<Vec<RleElement>>()
-        })
-        .chunks(2)
-        .into_iter()
-        .map(|mut it| {
-            let line = it.next().expect("No next iterator");
-            let new_line_or_end = it
-                .next()
-                .expect("No next iterator")
-                .get(0)
-                .expect("Missing newline element")
-                .clone();
-            (line, new_line_or_end)
-        })
-        // Convert from RleElements to Vec<Cell>
-        .map(|(line, newline_or_end): (Vec<RleElement> ,RleElement)| {
-            let complete_line = construct_line(line, width);
-            let newlines = if let RleElement::NewLine(x) = newline_or_end {
-                vec![ Cell::Dead; ((x-1) * width) as usize]
-            } else { vec![] };
-            let mut section = Vec::new();
-            section.extend(complete_line);
-            section.extend(newlines);
-            section
-        })
-        .flatten()
-        // Truncate
-        .take((width * height) as usize)
-        // Expand
-        .collect::<Vec<Cell>>()
-    ;
-    stream.extend(vec![ Cell::Dead; (width * height) as usize - stream.len()]);
-    stream
-}
\ No newline at end of file
+            items.push(run((end - start) as u32, 'o'));
+            col = cells[end - 1].0 + 1;
+            start = end;
+        }
+    }
+    items.push(String::from("!"));
+
+    let mut text = format!(
+        "x = {}, y = {}, rule = {}\n",
+        pattern.width(),
+        pattern.height(),
+        rule.unwrap_or("B3/S23")
+    );
+    let mut line_length = 0;
+    for item in items {
+        if line_length + item.len() > SAVE_LINE_LENGTH {
+            text.push('\n');
+            line_length = 0;
+        }
+        line_length += item.len();
+        text.push_str(&item);
+    }
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &str = "#N Glider\n#C A comment\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+
+    fn events(chunks: &[&[u8]]) -> Vec<RleEvent> {
+        let mut events = Vec::new();
+        let mut record = |event| events.push(event);
+        let mut parser = RleParser::new();
+        for chunk in chunks {
+            parser.feed(chunk, &mut record).unwrap();
+        }
+        parser.finish(&mut record).unwrap();
+        events
+    }
+
+    #[test]
+    fn parses_header_runs_and_lines() {
+        assert_eq!(
+            events(&[b"x = 4, y = 3\n2b2o$$12o!"]),
+            vec![
+                RleEvent::Header { width: 4, height: 3 },
+                RleEvent::Run { state: Cell::Dead, count: 2 },
+                RleEvent::Run { state: Cell::Alive, count: 2 },
+                RleEvent::NewLine(1),
+                RleEvent::NewLine(1),
+                RleEvent::Run { state: Cell::Alive, count: 12 },
+                RleEvent::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn chunk_boundaries_do_not_matter() {
+        let whole = events(&[GLIDER.as_bytes()]);
+        let bytes = GLIDER.as_bytes();
+        for split in 0..bytes.len() {
+            assert_eq!(events(&[&bytes[..split], &bytes[split..]]), whole, "split at {}", split);
+        }
+        let single_bytes = bytes.chunks(1).collect::<Vec<&[u8]>>();
+        assert_eq!(events(&single_bytes), whole);
+    }
+
+    #[test]
+    fn missing_end_marker_ends_the_pattern() {
+        assert_eq!(load_pattern("x = 2, y = 1\n2o"), load_pattern("x = 2, y = 1\n2o!"));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let errors = [
+            ("x = 2, y = 1\n2z!", "invalid rle character `z`"),
+            ("hello world", "invalid rle header line `hello world`"),
+            ("x = 2, y = 1\n2 o!", "cannot have whitespace inside a run"),
+            ("x = 2, y = 1\n2o2!", "cannot have a count before the end"),
+            ("x = 2, y = 1\n99999999999o!", "rle run count is too large"),
+            ("x = 99999999999, y = 1\no!", "error parsing x (`99999999999`)"),
+        ];
+        for &(text, error) in errors.iter() {
+            assert_eq!(load_pattern(text), Err(String::from(error)), "{:?}", text);
+            assert!(load(text).is_err(), "{:?}", text);
+        }
+        let error = load_pattern_reader(&b"x = 1, y = 1\n%"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        assert_eq!(load_pattern(""), Err(String::from("rle input has no header line")));
+        assert_eq!(load_pattern("#N Glider\n#C only comments"), Err(String::from("rle input has no header line")));
+        assert_eq!(load_pattern("x = 3, y"), Err(String::from("invalid rle header line `x = 3, y`")));
+        assert_eq!(load_pattern("x = 3, y = 3\nbo$2bo$3"), Err(String::from("rle input ends with a count")));
+    }
+
+    #[test]
+    fn errors_stop_the_parser() {
+        let mut events = Vec::new();
+        let mut parser = RleParser::new();
+        assert!(parser.feed(b"x = 2, y = 1\n?", &mut |event| events.push(event)).is_err());
+        assert!(parser.feed(b"2o!", &mut |event| events.push(event)).is_ok());
+        assert_eq!(events, vec![RleEvent::Header { width: 2, height: 1 }]);
+    }
+
+    #[test]
+    fn dense_grids_are_limited_in_size() {
+        assert_eq!(
+            load("x = 100000, y = 100000\no!").err(),
+            Some(String::from("pattern of 100000x100000 cells is too large to load"))
+        );
+        assert_eq!(load_pattern("x = 100000, y = 100000\no!").unwrap().population(), 1);
+    }
+
+    #[test]
+    fn grid_and_sparse_loaders_agree() {
+        let data = load(GLIDER).unwrap();
+        assert_eq!((data.width, data.height), (3, 3));
+        assert_eq!(Ok(Pattern::from_rle_data(&data)), load_pattern(GLIDER));
+        assert_eq!(Ok(load_pattern_reader(GLIDER.as_bytes()).unwrap()), load_pattern(GLIDER));
+    }
+
+    #[test]
+    fn save_round_trips() {
+        let pattern = load_pattern("x = 80, y = 5\n80o$$o78bo$b2o!").unwrap();
+        let text = save(&pattern, Some("B36/S23"));
+        assert!(text.lines().all(|line| line.len() <= SAVE_LINE_LENGTH));
+        assert_eq!(load_pattern(&text), Ok(pattern));
+        assert_eq!(read_rule(&text).as_deref(), Some("B36/S23"));
+    }
+
+    #[test]
+    fn reads_the_rule_from_the_header() {
+        assert_eq!(read_rule(GLIDER).as_deref(), Some("B3/S23"));
+        assert_eq!(read_rule("x = 1, y = 1\no!"), None);
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(feature = "web")]
+use wasm_bindgen::prelude::*;
+
+/// A rectangle of cells, by its top left cell and its size.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub row: u32,
+    pub col: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Selection {
+    pub fn new(row: u32, col: u32, width: u32, height: u32) -> Self {
+        Selection { row, col, width, height }
+    }
+
+    /// The rectangle with opposite corners `(row0, col0)` and `(row1, col1)`,
+    /// both included, given in any order.
+    pub fn from_corners(row0: u32, col0: u32, row1: u32, col1: u32) -> Self {
+        Selection {
+            row: row0.min(row1),
+            col: col0.min(col1),
+            width: col0.max(col1) - col0.min(col1) + 1,
+            height: row0.max(row1) - row0.min(row1) + 1,
+        }
+    }
+
+    pub fn contains(&self, row: u32, col: u32) -> bool {
+        row >= self.row && row - self.row < self.height && col >= self.col && col - self.col < self.width
+    }
+
+    /// The smallest rectangle holding this one and the cell at `(row, col)`.
+    pub fn extended(self, row: u32, col: u32) -> Self {
+        Selection::from_corners(
+            self.row.min(row),
+            self.col.min(col),
+            (self.row + self.height - 1).max(row),
+            (self.col + self.width - 1).max(col),
+        )
+    }
+
+    /// The part inside a `width` x `height` grid, if any.
+    pub fn clipped(self, width: u32, height: u32) -> Option<Self> {
+        let right = self.col.saturating_add(self.width).min(width);
+        let bottom = self.row.saturating_add(self.height).min(height);
+        if self.col >= right || self.row >= bottom {
+            return None;
+        }
+        Some(Selection::new(self.row, self.col, right - self.col, bottom - self.row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corners_may_be_given_in_any_order() {
+        let expected = Selection::new(1, 2, 4, 3);
+        assert_eq!(Selection::from_corners(1, 2, 3, 5), expected);
+        assert_eq!(Selection::from_corners(3, 5, 1, 2), expected);
+        assert_eq!(Selection::from_corners(1, 5, 3, 2), expected);
+        assert_eq!(Selection::from_corners(4, 4, 4, 4), Selection::new(4, 4, 1, 1));
+    }
+
+    #[test]
+    fn extending_covers_the_new_cell() {
+        let selection = Selection::new(2, 2, 2, 2);
+        assert_eq!(selection.extended(3, 3), selection);
+        assert_eq!(selection.extended(0, 5), Selection::new(0, 2, 4, 4));
+        assert_eq!(selection.extended(6, 1), Selection::new(2, 1, 3, 5));
+        assert!(selection.extended(6, 1).contains(6, 1));
+    }
+
+    #[test]
+    fn clipping_keeps_the_part_inside_the_grid() {
+        assert_eq!(Selection::new(1, 1, 2, 2).clipped(8, 8), Some(Selection::new(1, 1, 2, 2)));
+        assert_eq!(Selection::new(6, 5, 4, 4).clipped(8, 8), Some(Selection::new(6, 5, 3, 2)));
+        assert_eq!(Selection::new(1, 1, u32::MAX, u32::MAX).clipped(8, 8), Some(Selection::new(1, 1, 7, 7)));
+        assert_eq!(Selection::new(8, 0, 2, 2).clipped(8, 8), None);
+        assert_eq!(Selection::new(0, 0, 0, 3).clipped(8, 8), None);
+    }
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::UniverseConfig;
+use crate::universe::Cell;
+
+/// Identifies a binary snapshot.
+const MAGIC: &[u8; 4] = b"GOLS";
+
+/// The version of the snapshot format. Bump this whenever `Snapshot` changes
+/// shape, so that old snapshots are rejected rather than misread.
+const VERSION: u16 = 5;
+
+/// The complete state of a universe, apart from its canvas and history.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    pub width: u32,
+    pub height: u32,
+    pub generation: u32,
+    pub visible_rows: u32,
+    pub visible_columns: u32,
+    pub visible_row_start_position: u32,
+    pub visible_column_start_position: u32,
+    /// The zoom level, in pixels per cell.
+    pub cell_size: f64,
+    /// Cells packed eight to a byte, least significant bit first.
+    pub cells: Vec<u8>,
+    pub config: UniverseConfig,
+}
+
+/// Pack cells into a bit vector, eight cells to a byte.
+pub fn pack_cells(cells: &[Cell]) -> Vec<u8> {
+    cells
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (bit, &cell)| byte | ((cell as u8) << bit))
+        })
+        .collect()
+}
+
+/// Unpack `count` cells from a bit vector created by `pack_cells`.
+pub fn unpack_cells(packed: &[u8], count: usize) -> Result<Vec<Cell>, String> {
+    if packed.len() != count.div_ceil(8) {
+        return Err(format!("expected {} bytes of cells, found {}", count.div_ceil(8), packed.len()));
+    }
+    Ok((0..count)
+        .map(|i| if packed[i / 8] >> (i % 8) & 1 == 1 { Cell::Alive } else { Cell::Dead })
+        .collect())
+}
+
+impl Snapshot {
+    /// Encode as the versioned binary format: the magic bytes, the version as
+    /// a little endian u16, then the bincode encoded snapshot.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(&MAGIC[..]);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self).expect("snapshot serialization failed"));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 6 || &bytes[..4] != MAGIC {
+            return Err(String::from("not a universe snapshot"));
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != VERSION {
+            return Err(format!("unsupported snapshot version {} (expected {})", version, VERSION));
+        }
+        bincode::deserialize(&bytes[6..]).map_err(|e| format!("corrupt snapshot: {}", e))
+    }
+
+    /// Encode as JSON, which is larger but readable when debugging.
+    pub fn to_json(&self) -> String {
+        let value = serde_json::json!({
+            "version": VERSION,
+            "snapshot": self,
+        });
+        value.to_string()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct Versioned {
+            version: u16,
+            snapshot: serde_json::Value,
+        }
+        let versioned: Versioned = serde_json::from_str(json).map_err(|e| format!("corrupt snapshot: {}", e))?;
+        if versioned.version != VERSION {
+            return Err(format!("unsupported snapshot version {} (expected {})", versioned.version, VERSION));
+        }
+        serde_json::from_value(versioned.snapshot).map_err(|e| format!("corrupt snapshot: {}", e))
+    }
+}
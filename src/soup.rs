@@ -0,0 +1,342 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "web")]
+use wasm_bindgen::prelude::*;
+
+use crate::rle_loader;
+use crate::universe::Cell;
+
+/// Side length of an asymmetric (C1) soup, as used by apgsearch.
+const SOUP_SIZE: u32 = 16;
+
+/// The symmetry classes used by apgsearch. The suffix of each name describes
+/// where the centre of symmetry lies: `1` on the centre of a cell, `2` on the
+/// midpoint of a cell edge and `4` on a cell corner. `+` mirrors are
+/// orthogonal and `x` mirrors are diagonal.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Symmetry {
+    C1,
+    C2_1,
+    C2_2,
+    C2_4,
+    C4_1,
+    C4_4,
+    D2Plus1,
+    D2Plus2,
+    D2X,
+    D4Plus1,
+    D4Plus2,
+    D4Plus4,
+    D4X1,
+    D4X4,
+    D8_1,
+    D8_4,
+}
+
+/// A mapping of a cell to its image within a `width` x `height` soup.
+#[derive(Clone, Copy, Debug)]
+enum Transform {
+    /// Rotate by 180 degrees.
+    Rotate180,
+    /// Rotate by 90 degrees clockwise (square soups only).
+    Rotate90,
+    /// Mirror across the horizontal axis.
+    FlipRows,
+    /// Mirror across the vertical axis.
+    FlipColumns,
+    /// Mirror across the leading diagonal (square soups only).
+    Transpose,
+    /// Mirror across the anti-diagonal (square soups only).
+    AntiTranspose,
+}
+
+impl Transform {
+    fn apply(self, row: u32, col: u32, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Transform::Rotate180 => (height - 1 - row, width - 1 - col),
+            Transform::Rotate90 => (col, height - 1 - row),
+            Transform::FlipRows => (height - 1 - row, col),
+            Transform::FlipColumns => (row, width - 1 - col),
+            Transform::Transpose => (col, row),
+            Transform::AntiTranspose => (width - 1 - col, height - 1 - row),
+        }
+    }
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 16] = [
+        Symmetry::C1,
+        Symmetry::C2_1,
+        Symmetry::C2_2,
+        Symmetry::C2_4,
+        Symmetry::C4_1,
+        Symmetry::C4_4,
+        Symmetry::D2Plus1,
+        Symmetry::D2Plus2,
+        Symmetry::D2X,
+        Symmetry::D4Plus1,
+        Symmetry::D4Plus2,
+        Symmetry::D4Plus4,
+        Symmetry::D4X1,
+        Symmetry::D4X4,
+        Symmetry::D8_1,
+        Symmetry::D8_4,
+    ];
+
+    /// The apgsearch name of the symmetry, e.g. `D2_+1`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Symmetry::C1 => "C1",
+            Symmetry::C2_1 => "C2_1",
+            Symmetry::C2_2 => "C2_2",
+            Symmetry::C2_4 => "C2_4",
+            Symmetry::C4_1 => "C4_1",
+            Symmetry::C4_4 => "C4_4",
+            Symmetry::D2Plus1 => "D2_+1",
+            Symmetry::D2Plus2 => "D2_+2",
+            Symmetry::D2X => "D2_x",
+            Symmetry::D4Plus1 => "D4_+1",
+            Symmetry::D4Plus2 => "D4_+2",
+            Symmetry::D4Plus4 => "D4_+4",
+            Symmetry::D4X1 => "D4_x1",
+            Symmetry::D4X4 => "D4_x4",
+            Symmetry::D8_1 => "D8_1",
+            Symmetry::D8_4 => "D8_4",
+        }
+    }
+
+    /// The (width, height) of a soup with this symmetry. An odd side places
+    /// the centre of symmetry on a cell, an even side between cells.
+    pub fn soup_size(self) -> (u32, u32) {
+        let odd = 2 * SOUP_SIZE - 1;
+        let even = 2 * SOUP_SIZE;
+        match self {
+            Symmetry::C1 | Symmetry::D2X => (SOUP_SIZE, SOUP_SIZE),
+            Symmetry::C2_1 | Symmetry::C4_1 | Symmetry::D4Plus1 | Symmetry::D4X1 | Symmetry::D8_1 => (odd, odd),
+            Symmetry::C2_2 | Symmetry::D4Plus2 => (odd, even),
+            Symmetry::C2_4 | Symmetry::C4_4 | Symmetry::D4Plus4 | Symmetry::D4X4 | Symmetry::D8_4 => (even, even),
+            Symmetry::D2Plus1 => (SOUP_SIZE, odd),
+            Symmetry::D2Plus2 => (SOUP_SIZE, even),
+        }
+    }
+
+    /// The transforms that generate the symmetry group.
+    fn generators(self) -> &'static [Transform] {
+        match self {
+            Symmetry::C1 => &[],
+            Symmetry::C2_1 | Symmetry::C2_2 | Symmetry::C2_4 => &[Transform::Rotate180],
+            Symmetry::C4_1 | Symmetry::C4_4 => &[Transform::Rotate90],
+            Symmetry::D2Plus1 | Symmetry::D2Plus2 => &[Transform::FlipRows],
+            Symmetry::D2X => &[Transform::Transpose],
+            Symmetry::D4Plus1 | Symmetry::D4Plus2 | Symmetry::D4Plus4 => &[Transform::FlipRows, Transform::FlipColumns],
+            Symmetry::D4X1 | Symmetry::D4X4 => &[Transform::Transpose, Transform::AntiTranspose],
+            Symmetry::D8_1 | Symmetry::D8_4 => &[Transform::Rotate90, Transform::Transpose],
+        }
+    }
+}
+
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Symmetry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Symmetry::ALL
+            .iter()
+            .cloned()
+            .find(|symmetry| symmetry.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown symmetry `{}`", s))
+    }
+}
+
+/// A small splitmix64 generator, so that a soup can be reproduced from its
+/// seed on every platform.
+pub struct SoupRng {
+    state: u64,
+}
+
+impl SoupRng {
+    pub fn new(seed: u64) -> Self {
+        SoupRng { state: seed }
+    }
+
+    /// Seed the generator from the platform's source of randomness.
+    pub fn from_entropy() -> Self {
+        let mut seed = [0u8; 8];
+        getrandom::getrandom(&mut seed).expect("random seed generation failed");
+        SoupRng::new(u64::from_le_bytes(seed))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_bool(&mut self) -> bool {
+        self.next_u64() >> 63 == 1
+    }
+}
+
+/// A generated soup: a row-major `width` x `height` block of cells.
+pub struct Soup {
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<Cell>,
+}
+
+/// Generate a soup which is invariant under `symmetry`.
+///
+/// Cells are grouped into orbits under the symmetry group and each orbit is
+/// given a single random state, so every cell matches all of its images.
+pub fn generate(symmetry: Symmetry, rng: &mut SoupRng) -> Soup {
+    let (width, height) = symmetry.soup_size();
+    let mut states: Vec<Option<Cell>> = vec![None; (width * height) as usize];
+    let mut orbit = Vec::new();
+
+    for row in 0..height {
+        for col in 0..width {
+            if states[(row * width + col) as usize].is_some() {
+                continue;
+            }
+            let state = if rng.next_bool() { Cell::Alive } else { Cell::Dead };
+
+            orbit.push((row, col));
+            while let Some((row, col)) = orbit.pop() {
+                let idx = (row * width + col) as usize;
+                if states[idx].is_some() {
+                    continue;
+                }
+                states[idx] = Some(state);
+                for transform in symmetry.generators() {
+                    orbit.push(transform.apply(row, col, width, height));
+                }
+            }
+        }
+    }
+
+    let soup = Soup {
+        width,
+        height,
+        cells: states.into_iter().map(|state| state.unwrap()).collect(),
+    };
+    debug_assert!(soup.is_invariant(symmetry));
+    soup
+}
+
+impl Soup {
+    /// Check that the soup maps onto itself under every generator of
+    /// `symmetry`.
+    pub fn is_invariant(&self, symmetry: Symmetry) -> bool {
+        symmetry.generators().iter().all(|transform| {
+            (0..self.height).all(|row| {
+                (0..self.width).all(|col| {
+                    let (image_row, image_col) = transform.apply(row, col, self.width, self.height);
+                    self.cells[(row * self.width + col) as usize]
+                        == self.cells[(image_row * self.width + image_col) as usize]
+                })
+            })
+        })
+    }
+
+    /// Copy the soup into the centre of a `width` x `height` grid. Any part
+    /// of the soup that does not fit is clipped.
+    pub fn embed(&self, width: u32, height: u32) -> Vec<Cell> {
+        rle_loader::embed_centered(&self.cells, self.width, self.height, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::Pattern;
+    use crate::transform;
+
+    #[test]
+    fn soups_are_invariant_under_their_symmetry() {
+        for &symmetry in Symmetry::ALL.iter() {
+            for seed in 0..8 {
+                let soup = generate(symmetry, &mut SoupRng::new(seed));
+                assert_eq!((soup.width, soup.height), symmetry.soup_size());
+                assert!(soup.is_invariant(symmetry), "{} soup from seed {} is not symmetric", symmetry, seed);
+            }
+        }
+    }
+
+    /// The symmetries of each apgsearch soup, written out as whole-pattern
+    /// transforms, and one transform the soup is not expected to have.
+    fn expected_symmetries(symmetry: Symmetry) -> (Vec<transform::Transform>, Option<transform::Transform>) {
+        use transform::Transform::*;
+        match symmetry {
+            Symmetry::C1 => (vec![], Some(Rotate180)),
+            Symmetry::C2_1 | Symmetry::C2_2 | Symmetry::C2_4 => (vec![Rotate180], Some(FlipVertical)),
+            Symmetry::C4_1 | Symmetry::C4_4 => (vec![Rotate90, Rotate180, Rotate270], Some(FlipHorizontal)),
+            Symmetry::D2Plus1 | Symmetry::D2Plus2 => (vec![FlipVertical], Some(FlipHorizontal)),
+            Symmetry::D2X => (vec![Transpose], Some(AntiTranspose)),
+            Symmetry::D4Plus1 | Symmetry::D4Plus2 | Symmetry::D4Plus4 => {
+                (vec![FlipHorizontal, FlipVertical, Rotate180], Some(Transpose))
+            },
+            Symmetry::D4X1 | Symmetry::D4X4 => (vec![Transpose, AntiTranspose, Rotate180], Some(FlipHorizontal)),
+            Symmetry::D8_1 | Symmetry::D8_4 => (transform::Transform::ALL.to_vec(), None),
+        }
+    }
+
+    #[test]
+    fn soups_have_their_apgsearch_symmetries() {
+        for &symmetry in Symmetry::ALL.iter() {
+            let (symmetries, other) = expected_symmetries(symmetry);
+            let soups = (0..8)
+                .map(|seed| generate(symmetry, &mut SoupRng::new(seed)))
+                .map(|soup| Pattern::from_cells(soup.width, soup.height, &soup.cells))
+                .collect::<Vec<Pattern>>();
+            for soup in soups.iter() {
+                for &transform in symmetries.iter() {
+                    assert_eq!(soup.transformed(transform), *soup, "{} soup under {:?}", symmetry, transform);
+                }
+            }
+            if let Some(transform) = other {
+                assert!(
+                    soups.iter().any(|soup| soup.transformed(transform) != *soup),
+                    "{} soups are all symmetric under {:?}",
+                    symmetry,
+                    transform
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn is_invariant_rejects_a_lone_edge_cell() {
+        for &symmetry in Symmetry::ALL.iter().filter(|&&symmetry| symmetry != Symmetry::C1) {
+            let (width, height) = symmetry.soup_size();
+            let mut cells = vec![Cell::Dead; (width * height) as usize];
+            cells[1] = Cell::Alive;
+            assert!(!Soup { width, height, cells }.is_invariant(symmetry), "{}", symmetry);
+        }
+    }
+
+    #[test]
+    fn soups_are_reproducible_from_a_seed() {
+        let first = generate(Symmetry::D4Plus2, &mut SoupRng::new(42));
+        let second = generate(Symmetry::D4Plus2, &mut SoupRng::new(42));
+        assert_eq!(first.cells, second.cells);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for &symmetry in Symmetry::ALL.iter() {
+            assert_eq!(symmetry.name().parse::<Symmetry>(), Ok(symmetry));
+            assert_eq!(symmetry.to_string().to_lowercase().parse::<Symmetry>(), Ok(symmetry));
+        }
+        assert!("D3_1".parse::<Symmetry>().is_err());
+    }
+}
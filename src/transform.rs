@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "web")]
+use wasm_bindgen::prelude::*;
+
+use crate::pattern::Pattern;
+
+/// The eight symmetries of a square: the rotations and reflections that can
+/// be applied to a pattern. Rotations are clockwise.
+#[cfg_attr(feature = "web", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Transform {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Mirror left to right.
+    FlipHorizontal,
+    /// Mirror top to bottom.
+    FlipVertical,
+    /// Mirror across the leading diagonal, swapping rows and columns.
+    Transpose,
+    /// Mirror across the anti-diagonal.
+    AntiTranspose,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 8] = [
+        Transform::Identity,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+        Transform::Transpose,
+        Transform::AntiTranspose,
+    ];
+
+    /// Whether the transform swaps the width and height of a box.
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, Transform::Rotate90 | Transform::Rotate270 | Transform::Transpose | Transform::AntiTranspose)
+    }
+
+    /// The size of a `width` x `height` box after the transform.
+    pub fn output_size(self, width: u32, height: u32) -> (u32, u32) {
+        if self.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Map the cell at `(x, y)` in a `width` x `height` box to its position
+    /// in the transformed box.
+    pub fn apply(self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Transform::Identity => (x, y),
+            Transform::Rotate90 => (height - 1 - y, x),
+            Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+            Transform::Rotate270 => (y, width - 1 - x),
+            Transform::FlipHorizontal => (width - 1 - x, y),
+            Transform::FlipVertical => (x, height - 1 - y),
+            Transform::Transpose => (y, x),
+            Transform::AntiTranspose => (height - 1 - y, width - 1 - x),
+        }
+    }
+
+    /// The transform that undoes this one.
+    pub fn inverse(self) -> Transform {
+        match self {
+            Transform::Rotate90 => Transform::Rotate270,
+            Transform::Rotate270 => Transform::Rotate90,
+            other => other,
+        }
+    }
+}
+
+impl Pattern {
+    /// Apply a rotation or reflection to the pattern.
+    pub fn transformed(&self, transform: Transform) -> Pattern {
+        let (width, height) = transform.output_size(self.width(), self.height());
+        let cells = self
+            .cells()
+            .iter()
+            .map(|&(x, y)| transform.apply(x, y, self.width(), self.height()))
+            .collect();
+        Pattern::new(width, height, cells)
+    }
+
+    /// Move the cells of the pattern by `(dx, dy)` within its box. Cells that
+    /// leave the box are dropped.
+    pub fn translated(&self, dx: i64, dy: i64) -> Pattern {
+        let cells = self
+            .cells()
+            .iter()
+            .map(|&(x, y)| (x as i64 + dx, y as i64 + dy))
+            .filter(|&(x, y)| x >= 0 && y >= 0 && x < self.width() as i64 && y < self.height() as i64)
+            .map(|(x, y)| (x as u32, y as u32))
+            .collect();
+        Pattern::new(self.width(), self.height(), cells)
+    }
+
+    /// The normal form of the pattern over all eight symmetries, along with
+    /// the transform that produces it from this pattern. The pattern is
+    /// trimmed to its alive cells first, so patterns that differ only by
+    /// position, rotation or reflection share a normal form.
+    pub fn canonical(&self) -> (Pattern, Transform) {
+        let trimmed = self.trimmed();
+        Transform::ALL
+            .iter()
+            .map(|&transform| (trimmed.transformed(transform), transform))
+            .min_by(|(a, _), (b, _)| {
+                (a.height(), a.width(), a.cells()).cmp(&(b.height(), b.width(), b.cells()))
+            })
+            .unwrap()
+    }
+
+    /// Whether two patterns are the same up to position, rotation and
+    /// reflection.
+    pub fn is_equivalent(&self, other: &Pattern) -> bool {
+        self.canonical().0 == other.canonical().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An asymmetric 2x3 L so every transform gives a different result.
+    fn l_shape() -> Pattern {
+        Pattern::new(2, 3, vec![(0, 0), (0, 1), (0, 2), (1, 2)])
+    }
+
+    /// Move `pattern` to `(dx, dy)` inside a 6x6 box.
+    fn placed(pattern: &Pattern, dx: u32, dy: u32) -> Pattern {
+        Pattern::new(6, 6, pattern.cells().iter().map(|&(x, y)| (x + dx, y + dy)).collect())
+    }
+
+    #[test]
+    fn apply_stays_inside_the_output_box() {
+        for &transform in Transform::ALL.iter() {
+            let (width, height) = transform.output_size(2, 3);
+            for y in 0..3 {
+                for x in 0..2 {
+                    let (tx, ty) = transform.apply(x, y, 2, 3);
+                    assert!(tx < width && ty < height, "{:?} maps ({}, {}) outside", transform, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotations_are_clockwise() {
+        let rotated = l_shape().transformed(Transform::Rotate90);
+        assert_eq!((rotated.width(), rotated.height()), (3, 2));
+        assert_eq!(rotated, Pattern::new(3, 2, vec![(0, 0), (1, 0), (2, 0), (0, 1)]));
+    }
+
+    #[test]
+    fn inverse_undoes_each_transform() {
+        let pattern = l_shape();
+        for &transform in Transform::ALL.iter() {
+            assert_eq!(pattern.transformed(transform).transformed(transform.inverse()), pattern, "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn all_transforms_are_distinct() {
+        let pattern = l_shape();
+        let results: Vec<Pattern> = Transform::ALL.iter().map(|&t| pattern.transformed(t)).collect();
+        for (i, a) in results.iter().enumerate() {
+            assert!(results[i + 1..].iter().all(|b| a != b));
+        }
+    }
+
+    #[test]
+    fn translated_drops_cells_that_leave_the_box() {
+        let moved = l_shape().translated(1, -1);
+        assert_eq!(moved, Pattern::new(2, 3, vec![(1, 0), (1, 1)]));
+    }
+
+    #[test]
+    fn canonical_form_ignores_position_and_symmetry() {
+        let pattern = l_shape();
+        let (canonical, transform) = pattern.canonical();
+        assert_eq!(pattern.transformed(transform), canonical);
+        for &t in Transform::ALL.iter() {
+            let moved = placed(&pattern.transformed(t), 3, 2);
+            assert_eq!(moved.canonical().0, canonical);
+            assert!(moved.is_equivalent(&pattern));
+        }
+        assert!(!pattern.is_equivalent(&Pattern::new(2, 2, vec![(0, 0), (1, 1)])));
+    }
+}
@@ -1,32 +1,93 @@
+use std::fmt;
+
 use crate::utils;
 use crate::config;
+use crate::display::GridDisplay;
+use crate::composition;
+use crate::history;
+use crate::library;
+use crate::macrocell;
+use crate::pattern;
+use crate::render::{self, Renderer};
+use crate::selection::Selection;
+use crate::transform::Transform;
 use crate::rle_loader;
+use crate::soup;
+use crate::snapshot;
+
+use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "web")]
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use web_sys;
 use getrandom;
 
-#[wasm_bindgen]
+/// Once more than this fraction of the cells have changed since the last
+/// draw, a full redraw is cheaper than repainting them one by one.
+const DIRTY_LIMIT_DIVISOR: usize = 4;
+
+/// The smallest zoom level, in pixels per cell.
+pub const MIN_CELL_SIZE: f64 = 1.0 / 16.0;
+/// The largest zoom level, in pixels per cell.
+pub const MAX_CELL_SIZE: f64 = 256.0;
+
+/// The most cells of a macrocell pattern that are rasterized when no
+/// override size is set.
+const MAX_MACROCELL_WINDOW_CELLS: u64 = 1 << 24;
+
+/// The most cells a restored snapshot may have, so that a corrupt header
+/// cannot ask for an enormous allocation.
+const MAX_SNAPSHOT_CELLS: u32 = 1 << 28;
+
+#[cfg_attr(feature = "web", wasm_bindgen)]
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Cell {
     Dead = 0,
     Alive = 1
 }
 
-#[wasm_bindgen]
+impl Cell {
+    /// The state of the cell in the next generation, given its number of
+    /// living neighbors.
+    pub fn next_state(self, live_neighbors: u8) -> Cell {
+        match (self, live_neighbors) {
+            (Cell::Alive, x) if x < 2 => Cell::Dead,
+            (Cell::Alive, 2) | (Cell::Alive, 3) => Cell::Alive,
+            (Cell::Alive, x) if x > 3 => Cell::Dead,
+            (Cell::Dead, 3) => Cell::Alive,
+            (otherwise, _) => otherwise,
+        }
+    }
+}
+
+#[cfg_attr(feature = "web", wasm_bindgen)]
 pub struct Universe {
     width: u32,
     height: u32,
     cells: Vec<Cell>,
+    #[cfg(feature = "web")]
     canvas: Option<web_sys::HtmlCanvasElement>,
-    canvas_cell:Option<web_sys::HtmlCanvasElement>,
+    #[cfg(feature = "web")]
+    image_renderer: Option<render::ImageDataRenderer>,
     config: config::UniverseConfig,
     visible_rows: u32,
     visible_columns: u32,
     visible_row_start_position: u32,
     visible_column_start_position: u32,
+    /// The zoom level, in pixels per cell.
+    cell_size: f64,
+    /// Size in device pixels of the canvas, or of the grid for a headless
+    /// universe.
+    surface_width: u32,
+    surface_height: u32,
+    selection: Option<Selection>,
+    generation: u32,
+    history: history::History,
+    /// Indices of cells that changed since the last draw, possibly repeated.
+    dirty: Vec<usize>,
+    needs_full_redraw: bool,
+    #[cfg(feature = "web")]
+    drawn_canvas_size: (u32, u32),
 }
 
 /// Keep track of count of rows and columns
@@ -36,110 +97,99 @@ struct RowColCount {
 }
 
 impl Universe {
-    fn canvas_width(&self) -> u32 {
-        if let Some(canvas) = &self.canvas {
-            canvas.width()
-        } else {
-            0
-        }
-    }
+    // fn visible_grid_width(&self) -> u32 {
 
-    fn canvas_height(&self) -> u32 {
-        if let Some(canvas) = &self.canvas {
-            canvas.height()
-        } else {
-            0
-        }
-    }
+    // }
 
-    fn calculate_visible_grid_size(&self) -> RowColCount {
-        let canvas_width = self.canvas.as_ref().unwrap().width();
-        let canvas_height = self.canvas.as_ref().unwrap().height();
-        let line_width = self.config.border_width;
-        let cell_width = self.config.get_cell_size();
-        let cell_height = self.config.get_cell_size();
-
-        let visible_columns = if self.config.allow_overflow {
-            (canvas_width + line_width) / (cell_width + line_width)
-        } else {
-            let columns = (canvas_width + line_width) as f64 / (cell_width + line_width) as f64;
-            columns.ceil() as u32
+    /// Called when the size of the visible grid is known. Fails, leaving the
+    /// universe untouched, if the input pattern cannot be loaded.
+    fn build(&mut self, row_col_count: RowColCount) -> Result<(), String> {
+        // macrocells can hold patterns far too large to expand, so only a
+        // window of them is rasterized below
+        let input_macrocell = match self.config.get_input() {
+            config::UniverseInput::RleString(pattern) if rle_loader::detect_format(&pattern) == rle_loader::Format::Macrocell => {
+                Some(macrocell::load(&pattern)?)
+            },
+            _ => None,
         };
-        log!("visible_columns: {}", visible_columns);
-
-        let visible_rows = if self.config.allow_overflow {
-            (canvas_height + line_width) / (cell_height + line_width)
-        } else {
-            let rows = (canvas_height + line_width) as f64 / (cell_height + line_width) as f64;
-            rows.ceil() as u32
+        let input_pattern = match self.config.get_input() {
+            _ if input_macrocell.is_some() => None,
+            config::UniverseInput::RleString(pattern) => Some(rle_loader::load_any(&pattern)?),
+            config::UniverseInput::Named(name) => {
+                Some(library::load(&name).ok_or_else(|| format!("no pattern in the library named `{}`", name))?)
+            },
+            config::UniverseInput::Composition(placements) => Some(composition::compose(&placements)?),
+            _ => None,
         };
-        log!("visible_rows: {}", visible_rows);
-
-
-        RowColCount {
-            rows: visible_rows,
-            cols: visible_columns,
-        }
-    }
-
-    // fn visible_grid_width(&self) -> u32 {
-
-    // }
 
-    /// Called when a canvas is available
-    fn build(&mut self) {
-        // calculate the visibility of 
-        let row_col_count = self.calculate_visible_grid_size();
         self.visible_rows = row_col_count.rows;
         self.visible_columns = row_col_count.cols;
 
         let padding = self.config.get_padding();
 
-        if let Some((w, h)) = self.config.get_override_size() {
-            self.width = w + 2 * padding;
-            self.height = h + 2 * padding;
-            if self.width < self.visible_columns {
-                self.visible_columns = self.width;
-            }
-            if self.height < self.visible_rows {
-                self.visible_rows = self.height;
-            }
-            log!("Overriding visible_columns ({}) visible_rows({})", self.visible_columns, self.visible_rows);
-        } else {
+        // the universe is sized to the override size, else the pattern, else
+        // the canvas. A macrocell pattern is clipped to the canvas.
+        let (width, height) = match (self.config.get_override_size(), &input_pattern, &input_macrocell) {
+            (Some(size), _, _) => size,
+            (None, Some(pattern), _) => (pattern.width(), pattern.height()),
+            (None, None, Some(macrocell)) => {
+                let (_, _, width, height) = macrocell.bounding_box().unwrap_or((0, 0, 0, 0));
+                let clip = |size: u64, visible: u32| if visible > 0 { size.min(visible as u64) } else { size };
+                let (width, height) = (clip(width, self.visible_columns), clip(height, self.visible_rows));
+                if width.saturating_mul(height) > MAX_MACROCELL_WINDOW_CELLS {
+                    return Err(format!("macrocell window of {}x{} cells is too large, set an override size", width, height));
+                }
+                (width as u32, height as u32)
+            },
+            (None, None, None) => (self.visible_columns, self.visible_rows),
+        };
+        // an empty pattern still makes a single cell, so that edits have
+        // somewhere to wrap to
+        self.width = (width + 2 * padding).max(1);
+        self.height = (height + 2 * padding).max(1);
+        if self.width < self.visible_columns {
+            self.visible_columns = self.width;
+        }
+        if self.height < self.visible_rows {
+            self.visible_rows = self.height;
         }
 
+        self.generation = 0;
+        self.history.clear();
+        self.selection = None;
+        self.invalidate();
 
         self.visible_row_start_position = (self.height - self.visible_rows) / 2;
         self.visible_column_start_position = (self.width - self.visible_columns) / 2;
 
-        // Generate random cells
-        let mut rand_cells = vec![0u8; (self.width * self.height) as usize];
-        getrandom::getrandom(&mut rand_cells[..]).expect("random cell generation failed");
-        self.cells = rand_cells.into_iter().map(|cell| if cell%2==0 {Cell::Dead} else {Cell::Alive}).collect();
-
-
-        // self.cells = vec![Cell::Dead; (self.width * self.height) as usize];
-        // let cells = rle_loader::load_spaceships(self.width, self.height);
-    }
-
-    /// Check if the cell is within the visibility bounding box.
-    fn is_visible(&self, row: u32, col: u32) -> bool {
-        let row_lower = self.visible_row_start_position;
-        let row_upper = row_lower + self.visible_rows; // up to but not including
-        let col_lower = self.visible_column_start_position;
-        let col_upper = col_lower + self.visible_columns; // up to but not including
-        // log!("row_lower = {}", row_lower);
-        // log!("row_upper = {}", row_upper);
-        // log!("col_lower = {}", col_lower);
-        // log!("col_upper = {}", col_upper);
-        // log!("row = {}", row);
-        // log!("col = {}", col);
-
-        (row_lower <= row && row < row_upper) && (col_lower <= col && col < col_upper)
-    }
-
-    fn translate_to_visible_row_col(&self, row: u32, col:u32) -> (u32, u32) {
-        (row - self.visible_row_start_position, col - self.visible_column_start_position)
+        match self.config.get_input() {
+            config::UniverseInput::RleString(_) if input_macrocell.is_some() => {
+                // centre the window on the pattern
+                let macrocell = input_macrocell.unwrap();
+                let (x, y, width, height) = macrocell.bounding_box().unwrap_or((0, 0, 0, 0));
+                let x = x + (width / 2) as i64 - (self.width / 2) as i64;
+                let y = y + (height / 2) as i64 - (self.height / 2) as i64;
+                self.cells = macrocell.rasterize(x, y, self.width, self.height).cells;
+            },
+            config::UniverseInput::RleString(_) | config::UniverseInput::Named(_) | config::UniverseInput::Composition(_) => {
+                self.cells = input_pattern.unwrap().embed(self.width, self.height);
+            },
+            config::UniverseInput::Soup(symmetry, seed) => {
+                let mut rng = match seed {
+                    Some(seed) => soup::SoupRng::new(seed),
+                    None => soup::SoupRng::from_entropy(),
+                };
+                let soup = soup::generate(symmetry, &mut rng);
+                self.cells = soup.embed(self.width, self.height);
+            },
+            config::UniverseInput::Random => {
+                // Generate random cells
+                let mut rand_cells = vec![0u8; (self.width * self.height) as usize];
+                getrandom::getrandom(&mut rand_cells[..]).expect("random cell generation failed");
+                self.cells = rand_cells.into_iter().map(|cell| if cell%2==0 {Cell::Dead} else {Cell::Alive}).collect();
+            },
+        }
+        Ok(())
     }
 
     fn get_index(&self, row: u32, column: u32) -> usize {
@@ -162,9 +212,289 @@ impl Universe {
         }
         count
     }
+
+    fn to_snapshot(&self) -> snapshot::Snapshot {
+        snapshot::Snapshot {
+            width: self.width,
+            height: self.height,
+            generation: self.generation,
+            visible_rows: self.visible_rows,
+            visible_columns: self.visible_columns,
+            visible_row_start_position: self.visible_row_start_position,
+            visible_column_start_position: self.visible_column_start_position,
+            cell_size: self.cell_size,
+            cells: snapshot::pack_cells(&self.cells),
+            config: self.config.clone(),
+        }
+    }
+
+    /// Replace the state of the universe with a snapshot. The canvas is kept
+    /// and the history is cleared.
+    fn apply_snapshot(&mut self, snapshot: snapshot::Snapshot) -> Result<(), String> {
+        let count = match snapshot.width.checked_mul(snapshot.height) {
+            Some(count) if count > 0 && count <= MAX_SNAPSHOT_CELLS => count,
+            _ => return Err(format!("invalid universe size {}x{}", snapshot.width, snapshot.height)),
+        };
+        if !snapshot.cell_size.is_finite() {
+            return Err(format!("invalid cell size {}", snapshot.cell_size));
+        }
+        let fits = |start: u32, count: u32, size: u32| start.checked_add(count).is_some_and(|end| end <= size);
+        if !fits(snapshot.visible_row_start_position, snapshot.visible_rows, snapshot.height)
+            || !fits(snapshot.visible_column_start_position, snapshot.visible_columns, snapshot.width) {
+            return Err(String::from("visible region lies outside the universe"));
+        }
+        let cells = snapshot::unpack_cells(&snapshot.cells, count as usize)?;
+        self.width = snapshot.width;
+        self.height = snapshot.height;
+        self.generation = snapshot.generation;
+        self.visible_rows = snapshot.visible_rows;
+        self.visible_columns = snapshot.visible_columns;
+        self.visible_row_start_position = snapshot.visible_row_start_position;
+        self.visible_column_start_position = snapshot.visible_column_start_position;
+        self.cell_size = snapshot.cell_size.clamp(MIN_CELL_SIZE, MAX_CELL_SIZE);
+        self.cells = cells;
+        self.selection = None;
+        self.history = history::History::new(snapshot.config.get_history_limit());
+        self.invalidate();
+        self.config = snapshot.config;
+        Ok(())
+    }
+
+    /// Index of the cell at `(row, col)` counted from `(origin_row,
+    /// origin_col)`, wrapping around the edges of the universe.
+    fn get_wrapped_index(&self, origin_row: u32, origin_col: u32, row: i64, col: i64) -> usize {
+        let row = (origin_row as i64 + row).rem_euclid(self.height as i64) as u32;
+        let col = (origin_col as i64 + col).rem_euclid(self.width as i64) as u32;
+        self.get_index(row, col)
+    }
+
+    /// Copy the `width` x `height` region whose top left cell is at
+    /// `(row, col)` into a pattern.
+    fn region_pattern(&self, row: u32, col: u32, width: u32, height: u32) -> pattern::Pattern {
+        let mut cells = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if self.cells[self.get_wrapped_index(row, col, y as i64, x as i64)] == Cell::Alive {
+                    cells.push((x, y));
+                }
+            }
+        }
+        pattern::Pattern::new(width, height, cells)
+    }
+
+    /// Clear the regions of the given `(width, height)` sizes that share the
+    /// top left cell `(row, col)` and then set the alive cells, given as
+    /// `(x, y)` offsets from that cell, as a single edit.
+    fn replace_region(&mut self, row: u32, col: u32, regions: &[(u32, u32)], alive: &[(i64, i64)]) {
+        let targets = alive
+            .iter()
+            .map(|&(x, y)| self.get_wrapped_index(row, col, y, x))
+            .collect::<Vec<usize>>();
+        let clear = regions
+            .iter()
+            .flat_map(|&(width, height)| {
+                let (width, height) = (width.min(self.width), height.min(self.height));
+                (0..height).flat_map(move |y| (0..width).map(move |x| (x, y)))
+            })
+            .map(|(x, y)| self.get_wrapped_index(row, col, y as i64, x as i64))
+            .collect::<Vec<usize>>();
+        self.edit(|cells| {
+            for idx in clear {
+                cells[idx] = Cell::Dead;
+            }
+            for idx in targets {
+                cells[idx] = Cell::Alive;
+            }
+        });
+    }
+
+    /// Replace the selection, clipped to the universe, and repaint.
+    fn set_selection(&mut self, selection: Option<Selection>) {
+        self.selection = selection.and_then(|selection| selection.clipped(self.width, self.height));
+        self.invalidate();
+    }
+
+    /// Indices of the cells in the `width` x `height` region whose top left
+    /// cell is at `(row, col)`, wrapping around the edges of the universe.
+    /// The region is clipped to the size of the universe.
+    fn rect_indices(&self, row: i32, col: i32, width: u32, height: u32) -> Vec<usize> {
+        let (width, height) = (width.min(self.width), height.min(self.height));
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.get_wrapped_index(0, 0, row as i64 + y as i64, col as i64 + x as i64))
+            .collect()
+    }
+
+    /// Paste a pattern with its top left cell at `(row, col)` as a single
+    /// edit, wrapping around the edges of the universe.
+    fn paste(&mut self, pattern: &pattern::Pattern, row: i64, col: i64, mode: composition::PasteMode) {
+        let row = row.rem_euclid(self.height as i64) as u32;
+        let col = col.rem_euclid(self.width as i64) as u32;
+        if mode == composition::PasteMode::Copy {
+            let alive = pattern.cells().iter().map(|&(x, y)| (x as i64, y as i64)).collect::<Vec<(i64, i64)>>();
+            self.replace_region(row, col, &[(pattern.width(), pattern.height())], &alive);
+            return;
+        }
+        let targets = pattern
+            .cells()
+            .iter()
+            .map(|&(x, y)| self.get_wrapped_index(row, col, y as i64, x as i64))
+            .collect::<Vec<usize>>();
+        self.edit(|cells| {
+            for idx in targets {
+                cells[idx] = match (mode, cells[idx]) {
+                    (composition::PasteMode::Xor, Cell::Alive) => Cell::Dead,
+                    _ => Cell::Alive,
+                };
+            }
+        });
+    }
+
+    /// Copy the cells of the whole universe into a pattern.
+    pub fn to_pattern(&self) -> pattern::Pattern {
+        pattern::Pattern::from_cells(self.width, self.height, &self.cells)
+    }
+
+    /// The visible region and styling, for a renderer.
+    pub fn view(&self) -> render::View<'_> {
+        render::View {
+            width: self.width,
+            height: self.height,
+            cells: &self.cells,
+            visible_rows: self.visible_rows,
+            visible_columns: self.visible_columns,
+            visible_row_start_position: self.visible_row_start_position,
+            visible_column_start_position: self.visible_column_start_position,
+            cell_size: self.cell_size,
+            selection: self.selection,
+            config: &self.config,
+        }
+    }
+
+    /// Render the cells as text.
+    pub fn display(&self) -> GridDisplay<'_> {
+        GridDisplay::new(self.width, self.height, &self.cells)
+    }
+
+    /// Apply an edit to the cells, recording it so it can be undone.
+    pub fn edit<F: FnOnce(&mut [Cell])>(&mut self, f: F) {
+        let before = self.cells.clone();
+        f(&mut self.cells);
+        self.history.record_edit(&before, &self.cells);
+        self.record_changes(&before);
+    }
+
+    /// Set each cell at `targets` to `f` of its state as a single edit. Only
+    /// the cells that change are recorded, which keeps point and line edits
+    /// cheap on large universes.
+    fn edit_cells<F: Fn(Cell) -> Cell>(&mut self, targets: &[usize], f: F) {
+        let mut changed = Vec::new();
+        for &idx in targets {
+            let state = f(self.cells[idx]);
+            if state != self.cells[idx] {
+                self.cells[idx] = state;
+                changed.push(idx);
+            }
+        }
+        self.history.record_cells(&changed);
+        if !self.needs_full_redraw {
+            self.dirty.extend(changed);
+            self.limit_dirty();
+        }
+    }
+
+    /// Repaint everything on the next incremental draw.
+    fn invalidate(&mut self) {
+        self.needs_full_redraw = true;
+        self.dirty.clear();
+    }
+
+    /// Add the cells that differ from `before` to the dirty list.
+    fn record_changes(&mut self, before: &[Cell]) {
+        if self.needs_full_redraw {
+            return;
+        }
+        let changed = before.iter().zip(&self.cells).enumerate().filter(|(_, (a, b))| a != b).map(|(idx, _)| idx);
+        self.dirty.extend(changed);
+        self.limit_dirty();
+    }
+
+    fn limit_dirty(&mut self) {
+        if self.dirty.len() > self.cells.len() / DIRTY_LIMIT_DIVISOR {
+            self.invalidate();
+        }
+    }
+
+    /// The number of rows and columns that fit on the surface at the current
+    /// zoom level.
+    fn calculate_visible_grid_size(&self) -> RowColCount {
+        let (cell_size, line_width) = render::Layout::device_metrics(&self.config, self.cell_size);
+        let fit = |surface: u32| {
+            if surface == 0 {
+                return 0;
+            }
+            let count = (surface as f64 + line_width) / (cell_size + line_width);
+            if self.config.allow_overflow {
+                count.floor() as u32
+            } else {
+                count.ceil() as u32
+            }
+        };
+        let visible_columns = fit(self.surface_width);
+        log!("visible_columns: {}", visible_columns);
+        let visible_rows = fit(self.surface_height);
+        log!("visible_rows: {}", visible_rows);
+
+        RowColCount {
+            rows: visible_rows,
+            cols: visible_columns,
+        }
+    }
+
+    /// Recompute the visible rows and columns after the zoom level changes,
+    /// keeping the visible region inside the universe.
+    fn update_visible_region(&mut self) {
+        let RowColCount { rows, cols } = self.calculate_visible_grid_size();
+        self.visible_rows = rows.min(self.height);
+        self.visible_columns = cols.min(self.width);
+        self.set_visible_start(self.visible_row_start_position as i64, self.visible_column_start_position as i64);
+    }
+
+    /// Move the top left visible cell to `(row, col)`, clamped so that the
+    /// visible region stays inside the universe.
+    fn set_visible_start(&mut self, row: i64, col: i64) {
+        self.visible_row_start_position = row.max(0).min((self.height - self.visible_rows) as i64) as u32;
+        self.visible_column_start_position = col.max(0).min((self.width - self.visible_columns) as i64) as u32;
+    }
+
+    /// The zoom level at which `columns` x `rows` cells fill the surface,
+    /// rounded down to whole pixels per cell, or whole cells per pixel.
+    fn cell_size_to_fit(&self, columns: u32, rows: u32) -> f64 {
+        let ratio = self.config.device_pixel_ratio;
+        // the surface is in device pixels and the zoom level in CSS pixels
+        let fit = |line_width: f64| {
+            let width = (self.surface_width as f64 + line_width) / columns as f64 - line_width;
+            let height = (self.surface_height as f64 + line_width) / rows as f64 - line_width;
+            width.min(height) / ratio
+        };
+        let mut cell_size = fit(0.0);
+        if cell_size >= render::GRID_LINE_MIN_CELL_SIZE {
+            // leave room for the grid lines, and if the cells are then too
+            // small for lines, fit them without
+            let (_, line_width) = render::Layout::device_metrics(&self.config, cell_size);
+            let with_lines = fit(line_width);
+            cell_size = if with_lines >= render::GRID_LINE_MIN_CELL_SIZE {
+                with_lines
+            } else {
+                cell_size.min(render::GRID_LINE_MIN_CELL_SIZE - 1.0)
+            };
+        }
+        let cell_size = if cell_size >= 1.0 { cell_size.floor() } else { 1.0 / (1.0 / cell_size).ceil() };
+        cell_size.clamp(MIN_CELL_SIZE, MAX_CELL_SIZE)
+    }
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "web", wasm_bindgen)]
 impl Universe {
     /// Create a new Universe with default parameters.
     /// 
@@ -173,9 +503,15 @@ impl Universe {
     /// ```
     pub fn new() -> Self {
         utils::set_panic_hook();
+        let config = config::UniverseConfig::new();
         Universe {
+            #[cfg(feature = "web")]
             canvas: None,
-            config: config::UniverseConfig::new(),
+            #[cfg(feature = "web")]
+            image_renderer: None,
+            history: history::History::new(config.get_history_limit()),
+            cell_size: config.get_cell_size() as f64,
+            config,
             width: 0,
             height: 0,
             cells: vec![],
@@ -183,13 +519,26 @@ impl Universe {
             visible_columns: 0,
             visible_row_start_position: 0,
             visible_column_start_position: 0,
+            surface_width: 0,
+            surface_height: 0,
+            selection: None,
+            generation: 0,
+            dirty: vec![],
+            needs_full_redraw: true,
+            #[cfg(feature = "web")]
+            drawn_canvas_size: (0, 0),
         }
     }
 
     pub fn from(conf: config::UniverseConfig) -> Self {
         utils::set_panic_hook();
         Universe {
+            #[cfg(feature = "web")]
             canvas: None,
+            #[cfg(feature = "web")]
+            image_renderer: None,
+            history: history::History::new(conf.get_history_limit()),
+            cell_size: conf.get_cell_size() as f64,
             config: conf,
             width: 0,
             height: 0,
@@ -198,187 +547,988 @@ impl Universe {
             visible_rows: 0,
             visible_row_start_position: 0,
             visible_column_start_position: 0,
+            surface_width: 0,
+            surface_height: 0,
+            selection: None,
+            generation: 0,
+            dirty: vec![],
+            needs_full_redraw: true,
+            #[cfg(feature = "web")]
+            drawn_canvas_size: (0, 0),
         }
     }
     
-    /// Connects a Canvas DOM reference to the Universe and constructs the
-    /// internal data structures.
-    pub fn connect_canvas(&mut self, canvas: web_sys::HtmlCanvasElement) {
-        log!("{:?}", canvas);
-        self.canvas = Some(canvas);
-        self.build();
-        log!("width: {}, height: {}", self.canvas_width(), self.canvas_height());
+    /// Constructs the internal data structures for a universe that is not
+    /// drawn to a canvas, with `columns` x `rows` cells visible. Fails if the
+    /// input pattern cannot be loaded.
+    pub fn connect_headless(&mut self, columns: u32, rows: u32) -> Result<(), String> {
+        let (cell_size, line_width) = render::Layout::device_metrics(&self.config, self.cell_size);
+        let pitch = cell_size + line_width;
+        let size = |count: u32| (count as f64 * pitch - line_width).max(0.0).round() as u32;
+        self.surface_width = size(columns);
+        self.surface_height = size(rows);
+        self.build(RowColCount { rows, cols: columns })
     }
 
-    pub fn draw(&self) {
-        if let Some(canvas) = &self.canvas {
-            let context: web_sys::CanvasRenderingContext2d = canvas
-                .get_context("2d")
-                .unwrap()
-                .unwrap()
-                .dyn_into::<web_sys::CanvasRenderingContext2d>()
-                .unwrap();
-
-            use std::f64;
-            // Draw the outer circle.
-            // let cell_col_count = if self.config.allow_overlap {
-            //     0
-            // } else {
+    /// The zoom level, in pixels per cell. Below 1, several cells share a
+    /// pixel.
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
 
-            // }
+    /// Move the view by `dx` columns and `dy` rows, stopping at the edges of
+    /// the universe.
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.set_visible_start(
+            self.visible_row_start_position as i64 + dy as i64,
+            self.visible_column_start_position as i64 + dx as i64,
+        );
+        self.invalidate();
+    }
 
+    /// Scale the cells by `factor`, keeping the cell under the point
+    /// `(anchor_x, anchor_y)` of the canvas, in CSS pixels, in place.
+    ///   e.g. `universe.zoom(2.0, x, y)` zooms in on the mouse
+    pub fn zoom(&mut self, factor: f64, anchor_x: f64, anchor_y: f64) {
+        let anchor_x = anchor_x * self.config.device_pixel_ratio;
+        let anchor_y = anchor_y * self.config.device_pixel_ratio;
+        let before = render::Layout::new(&self.view(), self.surface_width, self.surface_height);
+        let pitch = before.cell_size + before.line_width;
+        let row = self.visible_row_start_position as f64 + (anchor_y - before.y_offset) / pitch;
+        let col = self.visible_column_start_position as f64 + (anchor_x - before.x_offset) / pitch;
 
-            let vis_col_count = self.visible_columns as f64;
-            let vis_row_count = self.visible_rows as f64;
-            let line_width = self.config.line_width as f64;
-            let border_width = self.config.border_width as f64;
-            let cell_size = self.config.get_cell_size() as f64;
+        self.cell_size = (self.cell_size * factor).clamp(MIN_CELL_SIZE, MAX_CELL_SIZE);
+        self.update_visible_region();
 
+        let after = render::Layout::new(&self.view(), self.surface_width, self.surface_height);
+        let pitch = after.cell_size + after.line_width;
+        self.set_visible_start(
+            (row - (anchor_y - after.y_offset) / pitch).round() as i64,
+            (col - (anchor_x - after.x_offset) / pitch).round() as i64,
+        );
+        self.invalidate();
+    }
 
-            let visible_grid_width = vis_col_count * (cell_size + line_width) - line_width;
-            let visible_grid_height = vis_row_count * (cell_size + line_width) - line_width;
+    /// Zoom and pan so that every alive cell is visible, as large as the
+    /// surface allows. An empty universe is centred without zooming.
+    pub fn fit_to_pattern(&mut self) {
+        let alive = (0..self.height)
+            .flat_map(|row| (0..self.width).map(move |col| (row, col)))
+            .filter(|&(row, col)| self.cells[self.get_index(row, col)] == Cell::Alive);
+        let bounds = alive.fold(None, |bounds, (row, col)| match bounds {
+            None => Some((row, col, row, col)),
+            Some((top, left, bottom, right)) => Some((top.min(row), left.min(col), bottom.max(row), right.max(col))),
+        });
+        let (top, left, bottom, right) = match bounds {
+            Some(bounds) => bounds,
+            None => {
+                self.center_on(self.height / 2, self.width / 2);
+                return;
+            },
+        };
+        if self.surface_width > 0 && self.surface_height > 0 {
+            self.cell_size = self.cell_size_to_fit(right - left + 1, bottom - top + 1);
+            self.update_visible_region();
+        }
+        self.center_on((top + bottom).div_ceil(2), (left + right).div_ceil(2));
+    }
 
-            // log!("vis_gid_wid {:.2}", visible_grid_width);
-            // log!("vis_gid_hei {:.2}", visible_grid_height);
+    /// Pan so that the cell at `(row, col)` is in the middle of the view, as
+    /// far as the edges of the universe allow.
+    pub fn center_on(&mut self, row: u32, col: u32) {
+        self.set_visible_start(
+            row as i64 - (self.visible_rows / 2) as i64,
+            col as i64 - (self.visible_columns / 2) as i64,
+        );
+        self.invalidate();
+    }
 
-            // calculate offsets
-            let x_offset: f64  = ((canvas.width() as f64 - visible_grid_width) / 2.0).floor();
-            let y_offset: f64  = ((canvas.height() as f64 - visible_grid_height) / 2.0).floor();
+    pub fn tick(&mut self) {
+        self.history.push(self.generation, &self.cells);
+        let mut next = self.cells.clone();
 
-            // log!("offsets x={:.2}, y={:.2}", x_offset, y_offset);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let idx = self.get_index(row, col);
+                let cell = self.cells[idx];
+                let live_neighbors = self.living_neighbor_count(row, col);
 
-            // draw border
-            context.begin_path();
-            context.set_stroke_style(&JsValue::from(self.config.get_line_color()));
-            context.set_line_width(border_width);
-            context.rect(
-                x_offset - border_width / 2.0,
-                y_offset - border_width / 2.0,
-                visible_grid_width + border_width,
-                visible_grid_height + border_width
-            );
-            context.stroke();
+                next[idx] = cell.next_state(live_neighbors);
+                if next[idx] != cell && !self.needs_full_redraw {
+                    self.dirty.push(idx);
+                }
+            }
+        }
+        self.cells = next;
+        self.generation += 1;
+        self.limit_dirty();
+    }
 
-            if self.config.allow_overflow {
+    /// The number of ticks since the universe was built.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
 
-            } else {
+    /// Return to the previous generation. Returns false if it is no longer
+    /// held in the history.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.pop() {
+            Some((generation, cells)) => {
+                self.generation = generation;
+                let before = std::mem::replace(&mut self.cells, cells);
+                self.record_changes(&before);
+                true
+            },
+            None => false,
+        }
+    }
 
+    /// Move to the given generation, restoring it from the history if it is
+    /// in the past or ticking forward if it is in the future. Returns false
+    /// if the generation is no longer held in the history.
+    pub fn rewind_to(&mut self, generation: u32) -> bool {
+        if generation >= self.generation {
+            while self.generation < generation {
+                self.tick();
             }
+            return true;
+        }
+        match self.history.rewind_to(generation) {
+            Some(cells) => {
+                self.generation = generation;
+                let before = std::mem::replace(&mut self.cells, cells);
+                self.record_changes(&before);
+                true
+            },
+            None => false,
+        }
+    }
 
-            // draw dividing lines
-            context.begin_path();
-            context.set_stroke_style(&JsValue::from(self.config.get_line_color()));
-            context.set_line_width(line_width);
-            for i in 1..(self.visible_columns) {
-                context.move_to(
-                    x_offset + line_width / 2.0 + i as f64 * (cell_size + line_width) - line_width,
-                    y_offset,
-                );
-                context.line_to(
-                    x_offset + line_width / 2.0 + i as f64 * (cell_size + line_width) - line_width,
-                    y_offset + visible_grid_height,
-                );
-                
+    /// Save the cells, dimensions, generation, visible region and
+    /// configuration in a compact versioned binary format.
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.to_snapshot().to_bytes()
+    }
+
+    /// Restore a snapshot created by `snapshot`.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let snapshot = snapshot::Snapshot::from_bytes(bytes)?;
+        self.apply_snapshot(snapshot)
+    }
+
+    /// Save the same state as `snapshot`, but as JSON for debugging.
+    pub fn snapshot_json(&self) -> String {
+        self.to_snapshot().to_json()
+    }
+
+    /// Restore a snapshot created by `snapshot_json`.
+    pub fn restore_json(&mut self, json: &str) -> Result<(), String> {
+        let snapshot = snapshot::Snapshot::from_json(json)?;
+        self.apply_snapshot(snapshot)
+    }
+
+    /// Replace the cells with a window of a macrocell pattern, so that
+    /// patterns too large to hold in full can be explored. The window is the
+    /// size of the universe and its top left cell is at `(x, y)` in the
+    /// pattern's coordinates.
+    pub fn load_macrocell_window(&mut self, text: &str, x: i64, y: i64) -> Result<(), String> {
+        let window = macrocell::load(text)?.rasterize(x, y, self.width, self.height);
+        self.edit(|cells| cells.copy_from_slice(&window.cells));
+        Ok(())
+    }
+
+    /// Save the cells as a macrocell file, recording the generation.
+    pub fn export_macrocell(&self) -> String {
+        let mut macrocell = macrocell::Macrocell::from_pattern(&self.to_pattern());
+        macrocell.generation = self.generation as u64;
+        macrocell::save(&macrocell)
+    }
+
+    /// Rotate or reflect the `width` x `height` region whose top left cell is
+    /// at `(row, col)`. The result keeps the same top left corner, so a
+    /// rotated non-square region covers `height` x `width` cells instead.
+    /// Regions wrap around the edges of the universe.
+    pub fn transform_region(&mut self, row: u32, col: u32, width: u32, height: u32, transform: Transform) {
+        let region = self.region_pattern(row, col, width, height).transformed(transform);
+        let alive = region.cells().iter().map(|&(x, y)| (x as i64, y as i64)).collect::<Vec<(i64, i64)>>();
+        // a rotation of a non-square region covers cells outside the original box
+        self.replace_region(row, col, &[(width, height), (region.width(), region.height())], &alive);
+    }
+
+    /// Move the cells of the `width` x `height` region whose top left cell is
+    /// at `(row, col)` by `dx` columns and `dy` rows, wrapping around the
+    /// edges of the universe.
+    pub fn translate_region(&mut self, row: u32, col: u32, width: u32, height: u32, dx: i32, dy: i32) {
+        let region = self.region_pattern(row, col, width, height);
+        let alive = region
+            .cells()
+            .iter()
+            .map(|&(x, y)| (x as i64 + dx as i64, y as i64 + dy as i64))
+            .collect::<Vec<(i64, i64)>>();
+        self.replace_region(row, col, &[(width, height)], &alive);
+    }
+
+    /// Paste a pattern, in any supported format, with the top left cell of
+    /// the transformed pattern at column `x` and row `y`. The pattern wraps
+    /// around the edges of the universe.
+    pub fn stamp(&mut self, pattern: &str, x: i32, y: i32, transform: Transform, mode: composition::PasteMode) -> Result<(), String> {
+        let pattern = rle_loader::load_any(pattern)?.transformed(transform);
+        self.paste(&pattern, y as i64, x as i64, mode);
+        Ok(())
+    }
+
+    /// Set the cell at `(row, col)`, wrapping around the edges of the
+    /// universe.
+    pub fn set_cell(&mut self, row: i32, col: i32, state: Cell) {
+        let idx = self.get_wrapped_index(0, 0, row as i64, col as i64);
+        self.edit_cells(&[idx], |_| state);
+    }
+
+    /// Flip the cell at `(row, col)` between alive and dead, wrapping around
+    /// the edges of the universe.
+    pub fn toggle_cell(&mut self, row: i32, col: i32) {
+        let idx = self.get_wrapped_index(0, 0, row as i64, col as i64);
+        self.edit_cells(&[idx], |cell| match cell {
+            Cell::Alive => Cell::Dead,
+            Cell::Dead => Cell::Alive,
+        });
+    }
+
+    /// Kill every cell.
+    pub fn clear(&mut self) {
+        self.edit(|cells| cells.iter_mut().for_each(|cell| *cell = Cell::Dead));
+    }
+
+    /// Set every cell of the `width` x `height` region whose top left cell is
+    /// at `(row, col)`, wrapping around the edges of the universe.
+    pub fn fill_rect(&mut self, row: i32, col: i32, width: u32, height: u32, state: Cell) {
+        let targets = self.rect_indices(row, col, width, height);
+        self.edit(|cells| {
+            for idx in targets {
+                cells[idx] = state;
             }
-            for i in 1..(self.visible_rows) {
-                context.move_to(
-                    x_offset,
-                    y_offset + line_width / 2.0 + i as f64 * (cell_size + line_width) - line_width,
-                );
-                context.line_to(
-                    x_offset + visible_grid_width,
-                    y_offset + line_width / 2.0 + i as f64 * (cell_size + line_width) - line_width,
-                );
-                
+        });
+    }
+
+    /// Give every cell of the `width` x `height` region whose top left cell
+    /// is at `(row, col)` a random state, wrapping around the edges of the
+    /// universe.
+    pub fn randomize_rect(&mut self, row: i32, col: i32, width: u32, height: u32) {
+        let targets = self.rect_indices(row, col, width, height);
+        let mut random = vec![0u8; targets.len()];
+        getrandom::getrandom(&mut random[..]).expect("random cell generation failed");
+        self.edit(|cells| {
+            for (idx, byte) in targets.into_iter().zip(random) {
+                cells[idx] = if byte % 2 == 0 { Cell::Dead } else { Cell::Alive };
             }
-            context.stroke();
-
-            // // draw cells
-            // context.begin_path();
-            // context.set_fill_style(&JsValue::from(self.config.get_cell_alive_color()));
-            // for col in 0..self.visible_columns {
-            //     for row in 0..self.visible_rows {
-            //         context.fill_rect(
-            //             x_offset + col as f64 * (cell_size + line_width),
-            //             y_offset + row as f64 * (cell_size + line_width),
-            //             cell_size,
-            //             cell_size
-            //         );
-            //     }
-            // }
-            // context.stroke();
-
-            // draw all dead cells
-            context.begin_path();
-            context.set_fill_style(&JsValue::from(self.config.get_cell_dead_color()));
-            for col in 0..self.width {
-                for row in 0..self.height {
-                    if ! self.is_visible(row, col) {
-                        continue;
-                    }
-                    if *self.cells.get(self.get_index(row, col)).unwrap() == Cell::Alive {
-                        continue;
-                    }
-                    let (row, col) = self.translate_to_visible_row_col(row, col);
-                    context.fill_rect(
-                        x_offset + col as f64 * (cell_size + line_width),
-                        y_offset + row as f64 * (cell_size + line_width),
-                        cell_size,
-                        cell_size
-                    );
+        });
+    }
+
+    /// Set the cells on the line from `(row0, col0)` to `(row1, col1)`, both
+    /// ends included, wrapping around the edges of the universe.
+    ///   e.g. join the cells under successive pointer events when painting
+    pub fn draw_line(&mut self, row0: i32, col0: i32, row1: i32, col1: i32, state: Cell) {
+        let targets = line_cells(row0 as i64, col0 as i64, row1 as i64, col1 as i64)
+            .into_iter()
+            .map(|(row, col)| self.get_wrapped_index(0, 0, row, col))
+            .collect::<Vec<usize>>();
+        self.edit_cells(&targets, |_| state);
+    }
+
+    /// The selected cells, if any.
+    pub fn selection(&self) -> Option<Selection> {
+        self.selection
+    }
+
+    /// Select the `width` x `height` region whose top left cell is at
+    /// `(row, col)`, clipped to the universe.
+    pub fn select(&mut self, row: u32, col: u32, width: u32, height: u32) {
+        self.set_selection(Some(Selection::new(row, col, width, height)));
+    }
+
+    /// Select the cells between two opposite corners, both included.
+    ///   e.g. from the cell where a drag started to the cell under the pointer
+    pub fn select_corners(&mut self, row0: u32, col0: u32, row1: u32, col1: u32) {
+        self.set_selection(Some(Selection::from_corners(row0, col0, row1, col1)));
+    }
+
+    /// Grow the selection to include the cell at `(row, col)`, or select just
+    /// that cell if nothing is selected.
+    pub fn extend_selection(&mut self, row: u32, col: u32) {
+        let selection = match self.selection {
+            Some(selection) => selection.extended(row, col),
+            None => Selection::new(row, col, 1, 1),
+        };
+        self.set_selection(Some(selection));
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.set_selection(None);
+    }
+
+    pub fn select_all(&mut self) {
+        self.select(0, 0, self.width, self.height);
+    }
+
+    /// Shrink the selection to the smallest rectangle holding its alive
+    /// cells. The selection is cleared if it has none.
+    pub fn shrink_selection(&mut self) {
+        let selection = match self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        let shrunk = (selection.row..selection.row + selection.height)
+            .flat_map(|row| (selection.col..selection.col + selection.width).map(move |col| (row, col)))
+            .filter(|&(row, col)| self.cells[self.get_index(row, col)] == Cell::Alive)
+            .fold(None, |shrunk: Option<Selection>, (row, col)| match shrunk {
+                Some(shrunk) => Some(shrunk.extended(row, col)),
+                None => Some(Selection::new(row, col, 1, 1)),
+            });
+        self.set_selection(shrunk);
+    }
+
+    /// Kill the selected cells.
+    pub fn clear_inside_selection(&mut self) {
+        if let Some(selection) = self.selection {
+            self.fill_rect(selection.row as i32, selection.col as i32, selection.width, selection.height, Cell::Dead);
+        }
+    }
+
+    /// Kill every cell that is not selected.
+    pub fn clear_outside_selection(&mut self) {
+        let selection = match self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        let width = self.width;
+        self.edit(|cells| {
+            for (idx, cell) in cells.iter_mut().enumerate() {
+                if !selection.contains(idx as u32 / width, idx as u32 % width) {
+                    *cell = Cell::Dead;
                 }
             }
-            context.stroke();
+        });
+    }
 
-            // draw all alive cells
-            context.begin_path();
-            context.set_fill_style(&JsValue::from(self.config.get_cell_alive_color()));
-            for col in 0..self.width {
-                for row in 0..self.height {
-                    if ! self.is_visible(row, col) {
-                        continue;
-                    }
-                    if *self.cells.get(self.get_index(row, col)).unwrap() == Cell::Dead {
-                        continue;
-                    }
-                    let (row, col) = self.translate_to_visible_row_col(row, col);
-                    context.fill_rect(
-                        x_offset + col as f64 * (cell_size + line_width),
-                        y_offset + row as f64 * (cell_size + line_width),
-                        cell_size,
-                        cell_size
-                    );
-                }
+    /// Give every selected cell a random state.
+    pub fn randomize_selection(&mut self) {
+        if let Some(selection) = self.selection {
+            self.randomize_rect(selection.row as i32, selection.col as i32, selection.width, selection.height);
+        }
+    }
+
+    /// Flip every selected cell between alive and dead.
+    pub fn invert_selection(&mut self) {
+        let selection = match self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        let targets = self.rect_indices(selection.row as i32, selection.col as i32, selection.width, selection.height);
+        self.edit(|cells| {
+            for idx in targets {
+                cells[idx] = match cells[idx] {
+                    Cell::Alive => Cell::Dead,
+                    Cell::Dead => Cell::Alive,
+                };
             }
-            context.stroke();
+        });
+    }
+
+    /// Rotate or reflect the selected cells in place, as `transform_region`
+    /// does. The selection follows the transformed cells.
+    pub fn transform_selection(&mut self, transform: Transform) {
+        let selection = match self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        self.transform_region(selection.row, selection.col, selection.width, selection.height, transform);
+        let (width, height) = transform.output_size(selection.width, selection.height);
+        self.select(selection.row, selection.col, width, height);
+    }
+
+    /// The selected cells as RLE text, if anything is selected.
+    pub fn copy_selection(&self) -> Option<String> {
+        let selection = self.selection?;
+        let pattern = self.region_pattern(selection.row, selection.col, selection.width, selection.height);
+        Some(rle_loader::save(&pattern, None))
+    }
+
+    /// Copy the selected cells as RLE text and then kill them.
+    pub fn cut_selection(&mut self) -> Option<String> {
+        let text = self.copy_selection()?;
+        self.clear_inside_selection();
+        Some(text)
+    }
+
+    /// Paste RLE text, or a pattern in any other supported format, with its
+    /// top left cell at `(row, col)`, and select the pasted region. The
+    /// pattern wraps around the edges of the universe.
+    pub fn paste_rle(&mut self, text: &str, row: u32, col: u32, mode: composition::PasteMode) -> Result<(), String> {
+        let pattern = rle_loader::load_any(text)?;
+        self.paste(&pattern, row as i64, col as i64, mode);
+        self.select(row, col, pattern.width(), pattern.height());
+        Ok(())
+    }
+
+    /// Revert the latest edit made in this generation.
+    pub fn undo(&mut self) -> bool {
+        let before = self.cells.clone();
+        let undone = self.history.undo(&mut self.cells);
+        self.record_changes(&before);
+        undone
+    }
+
+    /// Reapply the latest undone edit.
+    pub fn redo(&mut self) -> bool {
+        let before = self.cells.clone();
+        let redone = self.history.redo(&mut self.cells);
+        self.record_changes(&before);
+        redone
+    }
+
+    /// Draw the visible region into a `width` x `height` buffer of RGBA
+    /// pixels, as used by `ImageData`, for exporting images.
+    pub fn render_rgba(&self, width: u32, height: u32) -> Vec<u8> {
+        let mut renderer = render::FramebufferRenderer::new(width, height);
+        renderer.render(&self.view());
+        renderer.pixels
+    }
+
+    /// Draw the visible region as a `width` x `height` SVG document.
+    pub fn render_svg(&self, width: u32, height: u32) -> String {
+        let mut renderer = render::SvgRenderer::new(width, height);
+        renderer.render(&self.view());
+        renderer.document
+    }
+
+    /// The cell under the point `(x, y)` of the canvas, in CSS pixels, or
+    /// `None` if the point is on a grid line, the border or outside the
+    /// visible grid.
+    pub fn cell_at(&self, x: f64, y: f64) -> Option<render::CellPosition> {
+        let layout = render::Layout::new(&self.view(), self.surface_width, self.surface_height);
+        let ratio = self.config.device_pixel_ratio;
+        layout.visible_cell_at(x * ratio, y * ratio).map(|(row, col)| render::CellPosition {
+            row: self.visible_row_start_position + row,
+            col: self.visible_column_start_position + col,
+        })
+    }
+
+    /// Where the cell at `(row, col)` is drawn on the canvas, in CSS pixels,
+    /// or `None` if it is not visible.
+    pub fn cell_rect(&self, row: u32, col: u32) -> Option<render::CellRect> {
+        let visible_row = row.checked_sub(self.visible_row_start_position).filter(|&row| row < self.visible_rows)?;
+        let visible_col = col.checked_sub(self.visible_column_start_position).filter(|&col| col < self.visible_columns)?;
+        let layout = render::Layout::new(&self.view(), self.surface_width, self.surface_height);
+        let (x, y) = layout.cell_origin(visible_row, visible_col);
+        let ratio = self.config.device_pixel_ratio;
+        let size = layout.cell_size / ratio;
+        Some(render::CellRect { x: x / ratio, y: y / ratio, width: size, height: size })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The cell at `(row, col)`, wrapping around the edges of the universe.
+    pub fn cell(&self, row: i32, col: i32) -> Cell {
+        self.cells[self.get_wrapped_index(0, 0, row as i64, col as i64)]
+    }
+
+    pub fn cells(&self) -> *const Cell {
+        self.cells.as_ptr()
+    }
+}
+
+impl fmt::Display for Universe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display().fmt(f)
+    }
+}
+
+/// The cells on the line from `(row0, col0)` to `(row1, col1)`, both ends
+/// included, by Bresenham's algorithm.
+fn line_cells(row0: i64, col0: i64, row1: i64, col1: i64) -> Vec<(i64, i64)> {
+    let (d_row, d_col) = (-(row1 - row0).abs(), (col1 - col0).abs());
+    let (step_row, step_col) = ((row1 - row0).signum(), (col1 - col0).signum());
+    let (mut row, mut col) = (row0, col0);
+    let mut error = d_col + d_row;
+    let mut cells = Vec::new();
+    loop {
+        cells.push((row, col));
+        if (row, col) == (row1, col1) {
+            return cells;
+        }
+        let doubled = 2 * error;
+        if doubled >= d_row {
+            error += d_row;
+            col += step_col;
+        }
+        if doubled <= d_col {
+            error += d_col;
+            row += step_row;
         }
     }
+}
 
-    pub fn tick(&mut self) {
-        let mut next = self.cells.clone();
+/// The canvas renderer, only available in the browser.
+#[cfg(feature = "web")]
+impl Universe {
+    /// The dirty cells inside the visible region, as `(row, col)` within it.
+    /// The dirty list is emptied.
+    fn take_visible_dirty(&mut self) -> Vec<(u32, u32)> {
+        let mut dirty = std::mem::take(&mut self.dirty);
+        dirty.sort_unstable();
+        dirty.dedup();
+        dirty
+            .into_iter()
+            .map(|idx| (idx as u32 / self.width, idx as u32 % self.width))
+            .filter(|&(row, col)| {
+                row >= self.visible_row_start_position
+                    && row < self.visible_row_start_position + self.visible_rows
+                    && col >= self.visible_column_start_position
+                    && col < self.visible_column_start_position + self.visible_columns
+            })
+            .map(|(row, col)| (row - self.visible_row_start_position, col - self.visible_column_start_position))
+            .collect()
+    }
 
-        for row in 0..self.height {
-            for col in 0..self.width {
-                let idx = self.get_index(row, col);
-                let cell = self.cells[idx];
-                let live_neighbors = self.living_neighbor_count(row, col);
+    /// Fit the visible grid to the surface after it changes size, keeping the
+    /// centre of the view. With `grow`, a universe that is smaller than the
+    /// visible grid is enlarged, with its cells re-embedded in the centre.
+    /// The history is cleared when the universe grows.
+    fn reflow(&mut self, grow: bool) {
+        let mut center_row = self.visible_row_start_position + self.visible_rows / 2;
+        let mut center_col = self.visible_column_start_position + self.visible_columns / 2;
+        let RowColCount { rows, cols } = self.calculate_visible_grid_size();
+        let padding = self.config.get_padding();
+        if grow && self.config.get_override_size().is_none() && (cols > self.width || rows > self.height) {
+            let width = self.width.max(cols + 2 * padding);
+            let height = self.height.max(rows + 2 * padding);
+            // the offsets used by `Pattern::embed`
+            let row_offset = height / 2 - self.height / 2;
+            let col_offset = width / 2 - self.width / 2;
+            self.cells = self.to_pattern().embed(width, height);
+            self.selection = self
+                .selection
+                .map(|selection| Selection::new(selection.row + row_offset, selection.col + col_offset, selection.width, selection.height));
+            log!("universe grown from {}x{} to {}x{}", self.width, self.height, width, height);
+            self.width = width;
+            self.height = height;
+            self.history.clear();
+            center_row += row_offset;
+            center_col += col_offset;
+        }
+        self.update_visible_region();
+        self.center_on(center_row, center_col);
+    }
 
-                let next_cell = match (cell, live_neighbors) {
-                    (Cell::Alive, x) if x < 2 => Cell::Dead,
-                    (Cell::Alive, 2) | (Cell::Alive, 3) => Cell::Alive,
-                    (Cell::Alive, x) if x > 3 => Cell::Dead,
-                    (Cell::Dead, 3) => Cell::Alive,
-                    (otherwise, _) => otherwise,
+    fn canvas_width(&self) -> u32 {
+        if let Some(canvas) = &self.canvas {
+            canvas.width()
+        } else {
+            0
+        }
+    }
+
+    fn canvas_height(&self) -> u32 {
+        if let Some(canvas) = &self.canvas {
+            canvas.height()
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(feature = "web")]
+#[wasm_bindgen]
+impl Universe {
+    /// Connects a Canvas DOM reference to the Universe and constructs the
+    /// internal data structures. Fails if the input pattern cannot be loaded.
+    pub fn connect_canvas(&mut self, canvas: web_sys::HtmlCanvasElement) -> Result<(), String> {
+        log!("{:?}", canvas);
+        self.canvas = Some(canvas);
+        self.image_renderer = None;
+        self.surface_width = self.canvas_width();
+        self.surface_height = self.canvas_height();
+        let row_col_count = self.calculate_visible_grid_size();
+        self.build(row_col_count)?;
+        log!("width: {}, height: {}", self.canvas_width(), self.canvas_height());
+        Ok(())
+    }
+
+    /// Resize the canvas to `width` x `height` CSS pixels on a display with
+    /// `device_pixel_ratio` device pixels per CSS pixel. The backing store is
+    /// scaled to match, so the grid is drawn at the resolution of the display,
+    /// and the visible grid is reflowed around its centre. The cells are
+    /// kept.
+    ///   e.g. `universe.resize(rect.width, rect.height, window.devicePixelRatio)`
+    pub fn resize(&mut self, width: f64, height: f64, device_pixel_ratio: f64) {
+        let canvas = match &self.canvas {
+            Some(canvas) => canvas,
+            None => return,
+        };
+        canvas.set_width((width * device_pixel_ratio).round() as u32);
+        canvas.set_height((height * device_pixel_ratio).round() as u32);
+        let style = canvas.style();
+        style.set_property("width", &format!("{}px", width)).expect("cannot set canvas width");
+        style.set_property("height", &format!("{}px", height)).expect("cannot set canvas height");
+
+        self.config.device_pixel_ratio = device_pixel_ratio;
+        self.surface_width = self.canvas_width();
+        self.surface_height = self.canvas_height();
+        self.reflow(false);
+    }
+
+    /// Reflow the visible grid after the canvas changes size, keeping the
+    /// cells, unlike `connect_canvas` which rebuilds the universe. With
+    /// `grow`, a universe smaller than the canvas is enlarged to fill it
+    /// unless its size is fixed by `set_override_size`.
+    pub fn on_canvas_resized(&mut self, grow: bool) {
+        if self.canvas.is_none() {
+            return;
+        }
+        self.surface_width = self.canvas_width();
+        self.surface_height = self.canvas_height();
+        self.reflow(grow);
+    }
+
+    pub fn draw(&mut self) {
+        let canvas = match &self.canvas {
+            Some(canvas) => canvas,
+            None => return,
+        };
+        self.drawn_canvas_size = (canvas.width(), canvas.height());
+        match self.config.get_render_mode() {
+            render::RenderMode::Canvas => render::CanvasRenderer::new(canvas).render(&self.view()),
+            render::RenderMode::PixelBuffer => {
+                // the buffer is replaced when the canvas changes size
+                let mut renderer = match self.image_renderer.take() {
+                    Some(renderer) if (renderer.framebuffer.width, renderer.framebuffer.height) == (canvas.width(), canvas.height()) => renderer,
+                    _ => render::ImageDataRenderer::new(canvas),
                 };
+                renderer.render(&self.view());
+                self.image_renderer = Some(renderer);
+            },
+        }
+        self.needs_full_redraw = false;
+        self.dirty.clear();
+    }
 
-                next[idx] = next_cell;
+    /// Repaint only the cells that changed since the last draw. Everything is
+    /// repainted instead after the universe is rebuilt or restored, or the
+    /// canvas changes size.
+    pub fn draw_incremental(&mut self) {
+        let canvas_size = match &self.canvas {
+            Some(canvas) => (canvas.width(), canvas.height()),
+            None => return,
+        };
+        if self.needs_full_redraw || self.drawn_canvas_size != canvas_size {
+            self.draw();
+            return;
+        }
+        let cells = self.take_visible_dirty();
+        let canvas = self.canvas.as_ref().unwrap();
+        match self.config.get_render_mode() {
+            render::RenderMode::Canvas => render::CanvasRenderer::new(canvas).render_cells(&self.view(), &cells),
+            render::RenderMode::PixelBuffer => match self.image_renderer.take() {
+                Some(mut renderer) => {
+                    renderer.render_cells(&self.view(), &cells);
+                    self.image_renderer = Some(renderer);
+                },
+                None => self.draw(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_universe() -> Universe {
+        let mut universe = Universe::new();
+        universe.connect_headless(8, 8).unwrap();
+        universe.clear();
+        universe
+    }
+
+    fn alive(universe: &Universe) -> Vec<(i32, i32)> {
+        let mut alive = Vec::new();
+        for row in 0..universe.height() as i32 {
+            for col in 0..universe.width() as i32 {
+                if universe.cell(row, col) == Cell::Alive {
+                    alive.push((row, col));
+                }
             }
         }
-        self.cells = next
+        alive
     }
 
-    pub fn cells(&self) -> *const Cell {
-        self.cells.as_ptr()
+    #[test]
+    fn snapshots_round_trip() {
+        let mut universe = empty_universe();
+        universe.set_cell(1, 2, Cell::Alive);
+        let mut restored = Universe::new();
+        restored.restore(&universe.snapshot()).unwrap();
+        assert_eq!(alive(&restored), vec![(1, 2)]);
+        restored.restore_json(&universe.snapshot_json()).unwrap();
+        assert_eq!(alive(&restored), vec![(1, 2)]);
+    }
+
+    #[test]
+    fn invalid_snapshots_are_rejected() {
+        let mut universe = empty_universe();
+        let valid = universe.to_snapshot();
+        let cases: [fn(&mut snapshot::Snapshot); 4] = [
+            |snapshot| snapshot.width = 0,
+            |snapshot| snapshot.height = u32::MAX,
+            |snapshot| snapshot.cell_size = f64::NAN,
+            |snapshot| snapshot.visible_row_start_position = u32::MAX,
+        ];
+        for corrupt in cases.iter() {
+            let mut snapshot = universe.to_snapshot();
+            corrupt(&mut snapshot);
+            assert!(universe.apply_snapshot(snapshot).is_err());
+        }
+        assert!(universe.apply_snapshot(valid).is_ok());
+    }
+
+    #[test]
+    fn point_and_line_edits_can_be_undone() {
+        let mut universe = empty_universe();
+        universe.set_cell(0, 1, Cell::Alive);
+        universe.draw_line(0, 0, 0, 3, Cell::Alive);
+        universe.toggle_cell(0, 2);
+        assert_eq!(alive(&universe), vec![(0, 0), (0, 1), (0, 3)]);
+        assert!(universe.undo());
+        assert_eq!(alive(&universe), vec![(0, 0), (0, 1), (0, 2), (0, 3)]);
+        assert!(universe.undo());
+        assert_eq!(alive(&universe), vec![(0, 1)]);
+        assert!(universe.redo());
+        assert_eq!(alive(&universe), vec![(0, 0), (0, 1), (0, 2), (0, 3)]);
+    }
+
+    #[test]
+    fn rotating_a_region_clears_the_cells_it_covers() {
+        let mut universe = empty_universe();
+        // a 2 wide, 4 tall region with a cell in its bottom row, and a stray
+        // cell to the right that the rotated region will cover
+        universe.set_cell(3, 0, Cell::Alive);
+        universe.set_cell(0, 3, Cell::Alive);
+        universe.transform_region(0, 0, 2, 4, Transform::Rotate90);
+        assert_eq!(alive(&universe), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn transforming_the_selection_selects_the_result() {
+        let mut universe = empty_universe();
+        universe.set_cell(1, 2, Cell::Alive);
+        universe.set_cell(2, 0, Cell::Alive);
+        universe.select(0, 0, 3, 2);
+        universe.transform_selection(Transform::Rotate270);
+        assert_eq!(alive(&universe), vec![(0, 1)]);
+        let selection = universe.selection.unwrap();
+        assert_eq!((selection.width, selection.height), (2, 3));
+    }
+
+    #[test]
+    fn macrocell_input_loads_a_window() {
+        // 2^37 cells along the diagonal of a 2^40 wide square
+        let mut text = String::from("[M2]\n*$\n");
+        for level in 4..=40 {
+            text.push_str(&format!("{} {} 0 0 {}\n", level, level - 3, level - 3));
+        }
+        let mut universe = Universe::from(config::UniverseConfig::new().set_pattern_input(&text));
+        universe.connect_headless(8, 8).unwrap();
+        assert_eq!((universe.width(), universe.height()), (8, 8));
+        // the window centred on the pattern lands on the diagonal
+        assert_eq!(alive(&universe), vec![(0, 0)]);
+        let corner = -(1i64 << 39);
+        universe.load_macrocell_window(&text, corner + 8, corner + 5).unwrap();
+        assert_eq!(alive(&universe), vec![(3, 0)]);
+        assert!(rle_loader::load_any(&text).is_err());
+    }
+
+    #[test]
+    fn overlapping_stamps_follow_the_paste_mode() {
+        let block = "x = 2, y = 2\n2o$2o!";
+        let diagonal = "x = 2, y = 2\no$bo!";
+        for (mode, expected) in [
+            (composition::PasteMode::Or, vec![(0, 0), (0, 1), (1, 0), (1, 1)]),
+            (composition::PasteMode::Xor, vec![(0, 1), (1, 0)]),
+            (composition::PasteMode::Copy, vec![(0, 0), (1, 1)]),
+        ]
+        .iter()
+        {
+            let mut universe = empty_universe();
+            universe.stamp(block, 0, 0, Transform::Identity, *mode).unwrap();
+            universe.stamp(diagonal, 0, 0, Transform::Identity, *mode).unwrap();
+            assert_eq!(alive(&universe), *expected, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn named_and_soup_inputs_are_checked_when_set() {
+        assert!(config::UniverseConfig::new().set_named_input("no such pattern").is_err());
+        assert!(config::UniverseConfig::new().set_soup_input("D3_1").is_err());
+        assert!(config::UniverseConfig::new().set_seeded_soup_input("C5", 1).is_err());
+        let mut universe = Universe::from(config::UniverseConfig::new().set_named_input("Glider").unwrap());
+        universe.connect_headless(0, 0).unwrap();
+        assert_eq!(universe.to_pattern().population(), 5);
+    }
+
+    #[test]
+    fn shrinking_the_selection_fits_its_alive_cells() {
+        let mut universe = empty_universe();
+        universe.set_cell(2, 3, Cell::Alive);
+        universe.set_cell(4, 1, Cell::Alive);
+        universe.set_cell(7, 7, Cell::Alive);
+        universe.select(1, 0, 5, 5);
+        universe.shrink_selection();
+        assert_eq!(universe.selection, Some(Selection::new(2, 1, 3, 3)));
+        // nothing alive leaves nothing selected
+        universe.select(5, 2, 2, 2);
+        universe.shrink_selection();
+        assert_eq!(universe.selection, None);
     }
-}
 
+    #[test]
+    fn clearing_outside_the_selection_keeps_the_selected_cells() {
+        let mut universe = empty_universe();
+        universe.set_cell(0, 0, Cell::Alive);
+        universe.set_cell(2, 2, Cell::Alive);
+        universe.set_cell(3, 4, Cell::Alive);
+        universe.set_cell(7, 7, Cell::Alive);
+        universe.select(2, 2, 3, 2);
+        universe.clear_outside_selection();
+        assert_eq!(alive(&universe), vec![(2, 2), (3, 4)]);
+    }
+
+    #[test]
+    fn copied_selection_pastes_back_elsewhere() {
+        let mut universe = empty_universe();
+        universe.set_cell(1, 2, Cell::Alive);
+        universe.set_cell(2, 3, Cell::Alive);
+        universe.set_cell(3, 1, Cell::Alive);
+        universe.select(1, 1, 3, 3);
+        let text = universe.copy_selection().unwrap();
+        universe.clear();
+        universe.paste_rle(&text, 4, 3, composition::PasteMode::Or).unwrap();
+        assert_eq!(alive(&universe), vec![(4, 4), (5, 5), (6, 3)]);
+        assert_eq!(universe.selection, Some(Selection::new(4, 3, 3, 3)));
+        assert_eq!(universe.copy_selection(), Some(text));
+    }
+
+    /// A `size` x `size` universe with a 16x16 cell view of it.
+    fn viewport_universe(size: u32, device_pixel_ratio: f64) -> Universe {
+        let config = config::UniverseConfig::new()
+            .set_override_size(size, size)
+            .set_device_pixel_ratio(device_pixel_ratio);
+        let mut universe = Universe::from(config);
+        universe.connect_headless(16, 16).unwrap();
+        universe.clear();
+        universe
+    }
+
+    fn center(rect: render::CellRect) -> (f64, f64) {
+        (rect.x + rect.width / 2.0, rect.y + rect.height / 2.0)
+    }
+
+    #[test]
+    fn zooming_keeps_the_anchor_cell_in_place() {
+        for &ratio in [1.0, 1.5, 2.0].iter() {
+            for &factor in [2.0, 0.5, 3.0, 0.3, 8.0].iter() {
+                // large enough that the view is not stopped by the edges
+                let mut universe = viewport_universe(256, ratio);
+                universe.center_on(128, 128);
+                let (x, y) = center(universe.cell_rect(130, 123).unwrap());
+                universe.zoom(factor, x, y);
+                let cell = universe.cell_at(x, y).unwrap();
+                assert!(
+                    (cell.row as i32 - 130).abs() <= 1 && (cell.col as i32 - 123).abs() <= 1,
+                    "zooming by {} at ratio {} moved the anchor to {:?}",
+                    factor,
+                    ratio,
+                    cell
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zooming_and_panning_stay_inside_the_universe() {
+        let mut universe = viewport_universe(64, 1.0);
+        universe.pan(-100, 100);
+        assert_eq!((universe.visible_row_start_position, universe.visible_column_start_position), (48, 0));
+        universe.zoom(1e-6, 0.0, 0.0);
+        assert_eq!(universe.cell_size(), MIN_CELL_SIZE);
+        assert_eq!((universe.visible_rows, universe.visible_columns), (64, 64));
+        assert_eq!((universe.visible_row_start_position, universe.visible_column_start_position), (0, 0));
+        universe.zoom(1e6, 0.0, 0.0);
+        assert_eq!(universe.cell_size(), MAX_CELL_SIZE);
+    }
+
+    #[test]
+    fn fitting_shows_every_alive_cell() {
+        let mut universe = viewport_universe(64, 1.0);
+        universe.set_cell(5, 10, Cell::Alive);
+        universe.set_cell(50, 40, Cell::Alive);
+        universe.fit_to_pattern();
+        assert!(universe.cell_size() < 10.0);
+        assert!(universe.cell_rect(5, 10).is_some());
+        assert!(universe.cell_rect(50, 40).is_some());
+
+        // a small pattern is zoomed in on and centred
+        let mut universe = viewport_universe(64, 1.0);
+        universe.set_cell(60, 60, Cell::Alive);
+        universe.set_cell(61, 62, Cell::Alive);
+        universe.fit_to_pattern();
+        assert!(universe.cell_size() > 10.0);
+        assert!(universe.cell_rect(60, 60).is_some());
+        assert!(universe.cell_rect(61, 62).is_some());
+    }
+
+    #[test]
+    fn cell_at_finds_the_cell_drawn_by_cell_rect() {
+        for &ratio in [1.0, 1.5, 2.0].iter() {
+            let universe = viewport_universe(64, ratio);
+            // rects are in CSS pixels whatever the ratio
+            assert_eq!(universe.cell_rect(24, 24).unwrap().width, 10.0);
+            for &(row, col) in [(24, 24), (30, 33), (39, 39)].iter() {
+                let (x, y) = center(universe.cell_rect(row, col).unwrap());
+                assert_eq!(universe.cell_at(x, y), Some(render::CellPosition { row, col }), "at ratio {}", ratio);
+            }
+            // outside the view there is no rect and no cell
+            assert_eq!(universe.cell_rect(23, 30), None);
+            assert_eq!(universe.cell_at(-1.0, 10.0), None);
+        }
+    }
+
+    #[test]
+    fn points_on_grid_lines_have_no_cell() {
+        let universe = viewport_universe(64, 1.0);
+        let rect = universe.cell_rect(30, 33).unwrap();
+        let line = rect.x + rect.width + 0.5;
+        assert_eq!(universe.cell_at(line, rect.y + 1.0), None);
+        assert_eq!(universe.cell_at(rect.x + 1.0, rect.y - 0.5), None);
+        assert_eq!(universe.cell_at(rect.x + rect.width - 0.5, rect.y), Some(render::CellPosition { row: 30, col: 33 }));
+    }
+
+    #[test]
+    fn an_empty_pattern_makes_a_single_cell() {
+        let mut universe = Universe::from(config::UniverseConfig::new().set_pattern_input("x = 0, y = 0\n!"));
+        universe.connect_headless(0, 0).unwrap();
+        assert_eq!((universe.width(), universe.height()), (1, 1));
+        universe.set_cell(5, -3, Cell::Alive);
+        assert_eq!(alive(&universe), vec![(0, 0)]);
+        universe.toggle_cell(0, 0);
+        universe.stamp("x = 2, y = 1\n2o!", 0, 0, Transform::Identity, composition::PasteMode::Xor).unwrap();
+        assert_eq!(alive(&universe), vec![]);
+        universe.tick();
+        assert_eq!(universe.generation(), 1);
+    }
+}
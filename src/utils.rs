@@ -1,6 +1,11 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "web")]
 #[allow(unused_imports)]
 use web_sys;
 
+static LOG_ENABLED: AtomicBool = AtomicBool::new(true);
+
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -12,7 +17,20 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
+/// Turn native logging to stderr on or off, e.g. while a terminal front end
+/// owns the screen.
+pub fn set_log_enabled(enabled: bool) {
+    LOG_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn log_enabled() -> bool {
+    LOG_ENABLED.load(Ordering::Relaxed)
+}
+
 // A macro to provide `println!(..)`-style syntax for `console.log` logging.
+// The console only exists in the browser, so native builds with the `web`
+// feature fall back as well.
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
 #[macro_export]
 macro_rules! log {
     ( $( $t:tt )* ) => {
@@ -21,18 +39,14 @@ macro_rules! log {
     }
 }
 
-// use std::fmt;
-
-// impl fmt::Display for Universe {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         for line in self.cells.as_slice().chunks(self.width as usize) {
-//             for &cell in line {
-//                 let symbol = if cell == Cell::Dead { '◻' } else { '◼' };
-//                 write!(f, "{}", symbol)?;
-//             }
-//             write!(f, "\n")?;
-//         }
-//         Ok(())
-//     }
-// }
-
+// Without a browser console, log to stderr instead, unless logging has been
+// turned off.
+#[cfg(not(all(feature = "web", target_arch = "wasm32")))]
+#[macro_export]
+macro_rules! log {
+    ( $( $t:tt )* ) => {
+        if $crate::utils::log_enabled() {
+            eprintln!( $( $t )* );
+        }
+    }
+}